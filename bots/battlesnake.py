@@ -0,0 +1,96 @@
+#!/usr/bin/env python3
+"""An example bot for the Battlesnake API, to play against in --battlesnake games.
+
+Start one or more of them on ports of their own:
+
+    python3 bots/battlesnake.py 8001 &
+    python3 bots/battlesnake.py 8002 &
+
+and have the game engine run a match between them:
+
+    cargo run -- --battlesnake http://localhost:8001,http://localhost:8002
+
+The engine posts the game to `/start` once, to `/move` every turn, where we
+answer with `{"move": "up"}` or similar, and to `/end` when the game is over.
+Battlesnake counts `y` up from the bottom of the board, so `up` adds one to it.
+This bot takes whichever move that doesn't run into anything ends up closest
+to the food, preferring moves with the most room around them.
+"""
+
+import json
+import sys
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+MOVES = {"up": (0, 1), "down": (0, -1), "left": (-1, 0), "right": (1, 0)}
+
+
+def step(game, board, cell, move):
+    dx, dy = MOVES[move]
+    x, y = cell[0] + dx, cell[1] + dy
+    if game["ruleset"]["name"] == "wrapped":
+        return x % board["width"], y % board["height"]
+    if 0 <= x < board["width"] and 0 <= y < board["height"]:
+        return x, y
+    return None
+
+
+def room(game, board, start, taken):
+    """How many free cells can be reached from `start`, up to the snake's length."""
+    seen, todo = {start}, [start]
+    while todo and len(seen) < 64:
+        cell = todo.pop()
+        for move in MOVES:
+            nxt = step(game, board, cell, move)
+            if nxt is not None and nxt not in taken and nxt not in seen:
+                seen.add(nxt)
+                todo.append(nxt)
+    return len(seen)
+
+
+def choose(state):
+    game, board, me = state["game"], state["board"], state["you"]
+    taken = {(c["x"], c["y"]) for c in board["hazards"]}
+    for snake in board["snakes"]:
+        taken.update((c["x"], c["y"]) for c in snake["body"])
+    head = (me["head"]["x"], me["head"]["y"])
+
+    best, best_score = "up", None
+    for move in MOVES:
+        cell = step(game, board, head, move)
+        if cell is None or cell in taken:
+            continue
+        distance = min(
+            abs(cell[0] - f["x"]) + abs(cell[1] - f["y"]) for f in board["food"]
+        )
+        score = (min(room(game, board, cell, taken), me["length"]), -distance)
+        if best_score is None or score > best_score:
+            best, best_score = move, score
+    return best
+
+
+class Handler(BaseHTTPRequestHandler):
+    def do_GET(self):
+        self.answer({"apiversion": "1", "author": "snake", "color": "#ff8000"})
+
+    def do_POST(self):
+        length = int(self.headers.get("Content-Length", 0))
+        state = json.loads(self.rfile.read(length))
+        if self.path == "/move":
+            self.answer({"move": choose(state)})
+        else:
+            self.answer({})
+
+    def answer(self, body):
+        data = json.dumps(body).encode()
+        self.send_response(200)
+        self.send_header("Content-Type", "application/json")
+        self.send_header("Content-Length", str(len(data)))
+        self.end_headers()
+        self.wfile.write(data)
+
+    def log_message(self, *args):
+        pass
+
+
+port = int(sys.argv[1]) if len(sys.argv) > 1 else 8000
+ThreadingHTTPServer(("localhost", port), Handler).serve_forever()
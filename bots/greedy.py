@@ -0,0 +1,59 @@
+#!/usr/bin/env python3
+"""An example bot for the snake game's JSON lines protocol.
+
+Run it with `cargo run -- --bot "python3 bots/greedy.py"`, adding `--headless`
+to play without a window.
+
+Every tick the game writes the state of the board to our stdin as one line of
+JSON, like so (split over several lines here):
+
+    {"tick": 12, "width": 30, "height": 30, "boundary": "wrap", "you": 0,
+     "score": 3, "food": {"x": 4, "y": 9}, "walls": [],
+     "snakes": [{"head": {"x": 7, "y": 15}, "body": [{"x": 6, "y": 15}],
+                 "dir": "Right", "alive": true, "score": 1}]}
+
+and we answer with the direction we want to go, `Up`, `Down`, `Left` or
+`Right`, on a line of our own. This bot takes whichever move that doesn't run
+into anything ends up closest to the food.
+"""
+
+import json
+import sys
+
+MOVES = {"Up": (0, -1), "Down": (0, 1), "Left": (-1, 0), "Right": (1, 0)}
+OPPOSITE = {"Up": "Down", "Down": "Up", "Left": "Right", "Right": "Left"}
+
+
+def step(state, pos, move):
+    dx, dy = MOVES[move]
+    x, y = pos["x"] + dx, pos["y"] + dy
+    if state["boundary"] == "wrap":
+        return x % state["width"], y % state["height"]
+    if 0 <= x < state["width"] and 0 <= y < state["height"]:
+        return x, y
+    return None
+
+
+def choose(state):
+    me = state["snakes"][state["you"]]
+    taken = {(c["x"], c["y"]) for c in state["walls"]}
+    for snake in state["snakes"]:
+        taken.add((snake["head"]["x"], snake["head"]["y"]))
+        taken.update((c["x"], c["y"]) for c in snake["body"])
+
+    food = (state["food"]["x"], state["food"]["y"])
+    best, best_distance = me["dir"], None
+    for move in MOVES:
+        if move == OPPOSITE[me["dir"]]:
+            continue
+        cell = step(state, me["head"], move)
+        if cell is None or cell in taken:
+            continue
+        distance = abs(cell[0] - food[0]) + abs(cell[1] - food[1])
+        if best_distance is None or distance < best_distance:
+            best, best_distance = move, distance
+    return best
+
+
+for line in sys.stdin:
+    print(choose(json.loads(line)), flush=True)
@@ -0,0 +1,183 @@
+use crate::direction::Direction;
+use crate::grid_position::GridPosition;
+use crate::snake::Snake;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// What happens when the snake reaches the edge of the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Boundary {
+    /// The snake comes back in on the opposite side.
+    #[default]
+    Wrap,
+    /// The edge is a wall, and running into it ends the game.
+    Walls,
+}
+
+/// The `Board` describes the playing field the snake lives on. Keeping the
+/// dimensions here, rather than reading them from the global config, lets the
+/// game rules run without any knowledge of where the numbers came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Board {
+    pub width: u16,
+    pub height: u16,
+    pub boundary: Boundary,
+    /// Cells the snake can't go through, as laid out by a `Level`.
+    pub walls: HashSet<GridPosition>,
+    /// Where each snake's head starts out, and which way it starts out moving.
+    /// The first snake gets the first spawn, the second the second, and so on.
+    pub spawns: Vec<(GridPosition, Direction)>,
+    /// If not empty, food is only placed on these cells while any of them are free.
+    pub food_zones: Vec<GridPosition>,
+}
+
+impl Board {
+    /// An empty board. We put our snake a quarter of the way across it in the x
+    /// axis and half way down the y axis. This works well since we start out
+    /// moving to the right. A second snake starts across from it moving left,
+    /// and a third and fourth above and below them.
+    pub fn new(width: u16, height: u16, boundary: Boundary) -> Self {
+        Board {
+            width,
+            height,
+            boundary,
+            walls: HashSet::new(),
+            spawns: vec![
+                ((width / 4, height / 2).into(), Direction::Right),
+                ((width - 1 - width / 4, height / 2).into(), Direction::Left),
+                ((width / 2, height / 4).into(), Direction::Down),
+                ((width / 2, height - 1 - height / 4).into(), Direction::Up),
+            ],
+            food_zones: Vec::new(),
+        }
+    }
+
+    /// Where the snake of `player` (counting from 0) starts out, and which way
+    /// it is moving. If the board has fewer spawns than players, they take turns.
+    pub fn spawn(&self, player: usize) -> (GridPosition, Direction) {
+        self.spawns[player % self.spawns.len()]
+    }
+
+    /// Where we end up moving one cell from `pos` in the direction `dir`. With
+    /// `Boundary::Wrap` that is always somewhere on the board, but with
+    /// `Boundary::Walls` moving off the edge gives `None`.
+    pub fn step(&self, pos: GridPosition, dir: Direction) -> Option<GridPosition> {
+        if self.boundary == Boundary::Walls {
+            let off_edge = match dir {
+                Direction::Up => pos.y == 0,
+                Direction::Down => pos.y + 1 >= self.height,
+                Direction::Left => pos.x == 0,
+                Direction::Right => pos.x + 1 >= self.width,
+            };
+            if off_edge {
+                return None;
+            }
+        }
+        Some(GridPosition::new_from_move(pos, dir, self))
+    }
+
+    pub fn is_wall(&self, pos: GridPosition) -> bool {
+        self.walls.contains(&pos)
+    }
+
+    /// The number of cells on the board.
+    pub fn cells(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    /// How many moves it takes to get from `a` to `b` with nothing in the way,
+    /// going around the edge of the board if it wraps.
+    pub fn distance(&self, a: GridPosition, b: GridPosition) -> usize {
+        let along = |a: u16, b: u16, size: u16| {
+            let d = (a as i32 - b as i32).unsigned_abs() as usize;
+            match self.boundary {
+                Boundary::Wrap => d.min(size as usize - d),
+                Boundary::Walls => d,
+            }
+        };
+        along(a.x, b.x, self.width) + along(a.y, b.y, self.height)
+    }
+
+    /// A random `GridPosition` somewhere on the board.
+    pub fn random_position<R: Rng>(&self, rng: &mut R) -> GridPosition {
+        GridPosition::random(rng, self.width, self.height)
+    }
+
+    /// A random `GridPosition` that neither the `snakes` nor a wall are covering, or
+    /// `None` if the snakes fill the whole board. If the board has food zones we
+    /// pick from those, unless the snakes are covering all of them.
+    pub fn random_free_position<R: Rng>(
+        &self,
+        rng: &mut R,
+        snakes: &[Snake],
+    ) -> Option<GridPosition> {
+        if !self.food_zones.is_empty() {
+            if let Some(pos) = self.random_free_cell(rng, snakes, Some(&self.food_zones)) {
+                return Some(pos);
+            }
+        }
+        self.random_free_cell(rng, snakes, None)
+    }
+
+    /// Pick a random free cell from `zone`, or from the whole board if there's no zone.
+    ///
+    /// While most of the cells are free we just keep picking random cells until we
+    /// find a free one, which almost always succeeds on the first try or two. Once
+    /// the snakes cover half of them or more that gets slow, so instead we mark
+    /// every cell that is taken and pick uniformly among the ones left over.
+    fn random_free_cell<R: Rng>(
+        &self,
+        rng: &mut R,
+        snakes: &[Snake],
+        zone: Option<&[GridPosition]>,
+    ) -> Option<GridPosition> {
+        let candidates = zone.map_or(self.cells() - self.walls.len(), |zone| zone.len());
+        let taken_by_snakes: usize = snakes.iter().map(Snake::len).sum();
+        if taken_by_snakes * 2 < candidates {
+            loop {
+                let pos = match zone {
+                    Some(zone) => zone[rng.gen_range(0, zone.len())],
+                    None => self.random_position(rng),
+                };
+                if !self.is_wall(pos) && !snakes.iter().any(|snake| snake.covers(pos)) {
+                    return Some(pos);
+                }
+            }
+        }
+
+        let mut taken = vec![false; self.cells()];
+        let snake_cells = snakes.iter().flat_map(Snake::positions);
+        for pos in snake_cells.chain(self.walls.iter().copied()) {
+            taken[self.index(pos)] = true;
+        }
+        let free: Vec<GridPosition> = match zone {
+            Some(zone) => zone
+                .iter()
+                .copied()
+                .filter(|&pos| !taken[self.index(pos)])
+                .collect(),
+            None => (0..self.cells())
+                .filter(|&i| !taken[i])
+                .map(|i| self.position(i))
+                .collect(),
+        };
+        if free.is_empty() {
+            None
+        } else {
+            Some(free[rng.gen_range(0, free.len())])
+        }
+    }
+
+    /// Where the cell at `pos` lives in a row-major list of every cell on the board.
+    pub fn index(&self, pos: GridPosition) -> usize {
+        pos.y as usize * self.width as usize + pos.x as usize
+    }
+
+    /// The inverse of `index`.
+    pub fn position(&self, index: usize) -> GridPosition {
+        let width = self.width as usize;
+        ((index % width) as u16, (index / width) as u16).into()
+    }
+}
@@ -1,8 +1,8 @@
-use ggez::event::KeyCode;
+use serde::{Deserialize, Serialize};
 
 /// Next we create an enum that will represent all the possible
 /// directions that our snake could move.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -22,18 +22,4 @@ impl Direction {
             Direction::Right => Direction::Left,
         }
     }
-
-    /// We also create a helper function that will let us convert between a
-    /// `ggez` `Keycode` and the `Direction` that it represents. Of course,
-    /// not every keycode represents a direction, so we return `None` if this
-    /// is the case.
-    pub fn from_keycode(key: KeyCode) -> Option<Direction> {
-        match key {
-            KeyCode::Up => Some(Direction::Up),
-            KeyCode::Down => Some(Direction::Down),
-            KeyCode::Left => Some(Direction::Left),
-            KeyCode::Right => Some(Direction::Right),
-            _ => None,
-        }
-    }
 }
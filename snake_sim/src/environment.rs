@@ -0,0 +1,358 @@
+use crate::board::Board;
+use crate::direction::Direction;
+use crate::food::Ate;
+use crate::simulation::{Simulation, StepOutcome};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+
+/// How much an agent is rewarded, or punished, for what happens on each step.
+/// Every field can be set in the `[rewards]` table of Config.toml.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rewards {
+    /// For eating a piece of food.
+    pub food: f32,
+    /// For dying, whether into a wall or itself.
+    pub death: f32,
+    /// For filling the whole board.
+    pub win: f32,
+    /// For every step taken, usually a small penalty so that dawdling doesn't pay.
+    pub step: f32,
+    /// For every cell the head moves closer to the food, taken away again for
+    /// every cell it moves further from it.
+    pub closer: f32,
+}
+
+impl Default for Rewards {
+    fn default() -> Self {
+        Rewards {
+            food: 1.0,
+            death: -1.0,
+            win: 10.0,
+            step: -0.01,
+            closer: 0.0,
+        }
+    }
+}
+
+/// What an agent gets to see of the game: a stack of grids the size of the
+/// board, one per channel, laid out channel by channel and row by row so that
+/// `data[(channel * height + y) * width + x]` is the cell at `(x, y)`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Observation {
+    pub width: usize,
+    pub height: usize,
+    pub channels: usize,
+    pub data: Vec<f32>,
+}
+
+impl Observation {
+    /// 1 where the snake's head is.
+    pub const HEAD: usize = 0;
+    /// The snake's body, counting down from 1 just behind the head towards 0 at
+    /// the tail, so the agent can tell which way the body moves.
+    pub const BODY: usize = 1;
+    /// 1 where the food is.
+    pub const FOOD: usize = 2;
+    /// 1 on the walls of a level.
+    pub const WALLS: usize = 3;
+    pub const CHANNELS: usize = 4;
+
+    /// The observation of `simulation` as it stands.
+    pub fn new(simulation: &Simulation) -> Self {
+        let board = simulation.board();
+        let (width, height) = (board.width as usize, board.height as usize);
+        let mut observation = Observation {
+            width,
+            height,
+            channels: Observation::CHANNELS,
+            data: vec![0.0; Observation::CHANNELS * width * height],
+        };
+
+        let snake = simulation.snake();
+        observation.set(Observation::HEAD, snake.head.pos.x, snake.head.pos.y, 1.0);
+        let length = snake.body.len() as f32;
+        for (i, segment) in snake.body.iter().enumerate() {
+            let order = 1.0 - i as f32 / length;
+            observation.set(Observation::BODY, segment.pos.x, segment.pos.y, order);
+        }
+        let food = simulation.food().pos;
+        observation.set(Observation::FOOD, food.x, food.y, 1.0);
+        for wall in &board.walls {
+            observation.set(Observation::WALLS, wall.x, wall.y, 1.0);
+        }
+        observation
+    }
+
+    fn set(&mut self, channel: usize, x: u16, y: u16, value: f32) {
+        let i = (channel * self.height + y as usize) * self.width + x as usize;
+        self.data[i] = value;
+    }
+}
+
+/// Extra facts about a step that aren't part of the observation.
+#[derive(Clone, Debug, Serialize)]
+pub struct Info {
+    /// The seed of the game the step was taken in.
+    pub seed: u64,
+    pub score: usize,
+    /// The length of the snake, head included.
+    pub length: usize,
+    pub ticks: u64,
+    /// What the snake ate on this step, if anything.
+    pub ate: Option<Ate>,
+    /// Whether the snake filled the board.
+    pub won: bool,
+}
+
+/// A game of snake for training agents on, in the style of an OpenAI Gym
+/// environment. It plays by exactly the same rules as the game, since underneath
+/// it is the same `Simulation`, but it never opens a window.
+pub struct Environment {
+    board: Board,
+    rewards: Rewards,
+    simulation: Simulation,
+}
+
+impl Environment {
+    /// An environment playing on `board`, handing out `rewards`. It starts out
+    /// with a game seeded with 0; call `reset` to start another.
+    pub fn new(board: Board, rewards: Rewards) -> Self {
+        let simulation = Simulation::new(board.clone(), 0);
+        Environment {
+            board,
+            rewards,
+            simulation,
+        }
+    }
+
+    /// Start a new game seeded with `seed`.
+    pub fn reset(&mut self, seed: u64) -> Observation {
+        self.simulation = Simulation::new(self.board.clone(), seed);
+        Observation::new(&self.simulation)
+    }
+
+    /// Steer the snake towards `action` and advance the game one tick. Gives
+    /// what the agent sees now, its reward, whether the game is done, and some
+    /// more about what happened. Stepping a game that is done does nothing.
+    pub fn step(&mut self, action: Direction) -> (Observation, f32, bool, Info) {
+        let food = self.simulation.food().pos;
+        let before = self.board.distance(self.simulation.snake().head.pos, food);
+        let outcome = self.simulation.step(Some(action));
+
+        let rewards = &self.rewards;
+        let (reward, ate) = match outcome {
+            StepOutcome::GameOver => (0.0, None),
+            StepOutcome::Won => (rewards.step + rewards.food + rewards.win, Some(Ate::Food)),
+            StepOutcome::Ate(Ate::Food) => (rewards.step + rewards.food, Some(Ate::Food)),
+            StepOutcome::Ate(ate) => (rewards.step + rewards.death, Some(ate)),
+            StepOutcome::Moved => {
+                let after = self.board.distance(self.simulation.snake().head.pos, food);
+                let closer = before as f32 - after as f32;
+                (rewards.step + rewards.closer * closer, None)
+            }
+        };
+
+        let info = Info {
+            seed: self.simulation.seed(),
+            score: self.simulation.score(),
+            length: self.simulation.snake().len(),
+            ticks: self.simulation.ticks(),
+            ate,
+            won: self.simulation.has_won(),
+        };
+        let observation = Observation::new(&self.simulation);
+        (observation, reward, self.simulation.is_over(), info)
+    }
+}
+
+/// A batch of environments that are all stepped together, so an agent can
+/// gather experience from many games at once. When one of the games ends a new
+/// one is started in its place straight away, so every step gives an
+/// observation of a game in progress; the `done` flag and `info` still describe
+/// the game that just ended.
+pub struct VecEnvironment {
+    environments: Vec<Environment>,
+    /// The seed the next game to be started will get.
+    next_seed: u64,
+}
+
+impl VecEnvironment {
+    pub fn new(count: usize, board: Board, rewards: Rewards) -> Self {
+        VecEnvironment {
+            environments: (0..count)
+                .map(|_| Environment::new(board.clone(), rewards))
+                .collect(),
+            next_seed: 0,
+        }
+    }
+
+    /// Start a new game in every environment. They are seeded one after another
+    /// counting up from `seed`, as is every game started after them.
+    pub fn reset(&mut self, seed: u64) -> Vec<Observation> {
+        self.next_seed = seed;
+        let mut observations = Vec::new();
+        for i in 0..self.environments.len() {
+            let seed = self.take_seed();
+            observations.push(self.environments[i].reset(seed));
+        }
+        observations
+    }
+
+    /// Step every environment with its own action from `actions`, which should
+    /// have one for each of them.
+    pub fn step(&mut self, actions: &[Direction]) -> Vec<(Observation, f32, bool, Info)> {
+        let mut steps = Vec::new();
+        for (i, &action) in actions.iter().enumerate().take(self.environments.len()) {
+            let (mut observation, reward, done, info) = self.environments[i].step(action);
+            if done {
+                let seed = self.take_seed();
+                observation = self.environments[i].reset(seed);
+            }
+            steps.push((observation, reward, done, info));
+        }
+        steps
+    }
+
+    /// How many environments there are.
+    pub fn len(&self) -> usize {
+        self.environments.len()
+    }
+
+    /// Whether there are no environments at all.
+    pub fn is_empty(&self) -> bool {
+        self.environments.is_empty()
+    }
+
+    fn take_seed(&mut self) -> u64 {
+        let seed = self.next_seed;
+        self.next_seed = self.next_seed.wrapping_add(1);
+        seed
+    }
+}
+
+/// A request sent to `serve`, one JSON object per line.
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Request {
+    /// `{"reset": 1234}` starts new games seeded from 1234.
+    Reset(u64),
+    /// `{"step": ["Up", "Left"]}` steps each environment with its action.
+    Step(Vec<Direction>),
+}
+
+/// One environment's part of the answer to a step request.
+#[derive(Serialize)]
+struct Transition {
+    observation: Observation,
+    reward: f32,
+    done: bool,
+    info: Info,
+}
+
+/// Run `environments` for an agent in another process, such as a Python
+/// training script, talking JSON lines over stdin and stdout. Each request
+/// line is answered with a line holding a list with one entry per environment:
+/// an observation for a reset, or a transition with the observation, reward,
+/// done flag and info for a step. Malformed requests are answered with an
+/// `{"error": ...}` line. This goes on until stdin is closed.
+pub fn serve(environments: &mut VecEnvironment) -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let answer = match serde_json::from_str(&line) {
+            Ok(Request::Reset(seed)) => serde_json::to_string(&environments.reset(seed)),
+            Ok(Request::Step(actions)) if actions.len() != environments.len() => {
+                serde_json::to_string(&serde_json::json!({
+                    "error": format!(
+                        "Expected {} actions, one for each environment, but got {}",
+                        environments.len(),
+                        actions.len()
+                    )
+                }))
+            }
+            Ok(Request::Step(actions)) => {
+                let transitions: Vec<Transition> = environments
+                    .step(&actions)
+                    .into_iter()
+                    .map(|(observation, reward, done, info)| Transition {
+                        observation,
+                        reward,
+                        done,
+                        info,
+                    })
+                    .collect();
+                serde_json::to_string(&transitions)
+            }
+            Err(e) => serde_json::to_string(&serde_json::json!({ "error": e.to_string() })),
+        }?;
+        writeln!(out, "{}", answer)?;
+        out.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Boundary;
+
+    fn board() -> Board {
+        Board::new(6, 6, Boundary::Walls)
+    }
+
+    #[test]
+    fn resetting_seeds_the_games_one_after_another() {
+        let mut environments = VecEnvironment::new(2, board(), Rewards::default());
+        let observations = environments.reset(10);
+        assert_eq!(observations.len(), environments.len());
+        for (seed, observation) in (10..).zip(&observations) {
+            let expected = Observation::new(&Simulation::new(board(), seed));
+            assert_eq!(observation.data, expected.data);
+        }
+
+        let dir = Simulation::new(board(), 0).snake().dir;
+        for (seed, (_, _, done, info)) in (10..).zip(environments.step(&[dir, dir])) {
+            assert!(!done);
+            assert_eq!((info.seed, info.ticks, info.length), (seed, 1, 2));
+        }
+    }
+
+    #[test]
+    fn games_that_end_are_started_again_straight_away() {
+        let rewards = Rewards::default();
+        let mut environments = VecEnvironment::new(2, board(), rewards);
+        environments.reset(10);
+
+        // Both snakes start in the same place, so heading straight on runs them
+        // into the wall on the same tick.
+        let dir = Simulation::new(board(), 0).snake().dir;
+        let steps = loop {
+            let steps = environments.step(&[dir, dir]);
+            if steps.iter().any(|(_, _, done, _)| *done) {
+                break steps;
+            }
+            assert!(steps[0].3.ticks < 6, "the snakes never reached the wall");
+        };
+
+        for (seed, (observation, reward, done, info)) in (10..).zip(&steps) {
+            assert!(done);
+            assert_eq!(info.seed, seed);
+            assert_eq!(info.ate, Some(Ate::Wall));
+            assert_eq!(*reward, rewards.step + rewards.death);
+            // What we see is already the game that took its place.
+            let next = Observation::new(&Simulation::new(board(), seed + 2));
+            assert_eq!(observation.data, next.data);
+        }
+
+        for (seed, (_, _, done, info)) in (12..).zip(environments.step(&[dir, dir])) {
+            assert!(!done);
+            assert_eq!((info.seed, info.ticks), (seed, 1));
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use crate::grid_position::GridPosition;
+use serde::{Deserialize, Serialize};
+
+/// This is again an abstraction over a `GridPosition` that represents
+/// a piece of food the snake can eat.
+#[derive(Clone)]
+pub struct Food {
+    pub pos: GridPosition,
+}
+
+impl Food {
+    pub fn new(pos: GridPosition) -> Self {
+        Food { pos }
+    }
+}
+
+/// Here we define an enum of the possible things that the snake could have "eaten"
+/// during an update of the game. It could have either eaten a piece of `Food`,
+/// it could have eaten `Itself` if the head ran into its body, it could have
+/// run into a `Wall`, or it could have run into another `Snake`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ate {
+    Itself,
+    Food,
+    Wall,
+    Snake,
+}
+
+impl Ate {
+    /// Whether eating this kills the snake.
+    pub fn is_fatal(&self) -> bool {
+        *self != Ate::Food
+    }
+}
@@ -0,0 +1,102 @@
+use crate::board::Board;
+use crate::direction::Direction;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Now we define a struct that will hold an entity's position on our game board
+/// or grid which we defined above. We'll use signed integers because we only want
+/// to store whole numbers, and we need them to be signed so that they work properly
+/// with our modulus arithmetic later.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct GridPosition {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl GridPosition {
+    /// We make a standard helper function so that we can create a new `GridPosition`
+    /// more easily.
+    pub fn new(x: u16, y: u16) -> Self {
+        GridPosition { x, y }
+    }
+
+    /// As well as a helper function that will give us a random `GridPosition` from
+    /// `(0, 0)` to `(max_x, max_y)`, drawn from the given random number generator.
+    pub fn random<R: Rng>(rng: &mut R, max_x: u16, max_y: u16) -> Self {
+        // We can use `.into()` to convert from `(u16, u16)` to a `GridPosition` since
+        // we implement `From<(u16, u16)>` for `GridPosition` below.
+        (
+            rng.gen_range::<u16, u16, u16>(0, max_x),
+            rng.gen_range::<u16, u16, u16>(0, max_y),
+        )
+            .into()
+    }
+
+    /// We'll make another helper function that takes one grid position and returns a new one after
+    /// making one move in the direction of `dir`, wrapping around within our grid
+    /// size if the move would have otherwise moved us off the board to the top,
+    /// bottom, left, or right. Going up or down wraps at the board's height, and
+    /// going left or right at its width, so boards needn't be square.
+    pub fn new_from_move(pos: GridPosition, dir: Direction, board: &Board) -> Self {
+        match dir {
+            Direction::Up => {
+                GridPosition::new(pos.x, pos.y.checked_sub(1).unwrap_or(board.height - 1))
+            }
+            Direction::Down => GridPosition::new(pos.x, (pos.y + 1) % board.height),
+            Direction::Left => {
+                GridPosition::new(pos.x.checked_sub(1).unwrap_or(board.width - 1), pos.y)
+            }
+            Direction::Right => GridPosition::new((pos.x + 1) % board.width, pos.y),
+        }
+    }
+}
+
+/// And here we implement the `From` trait to allow us to easily convert between
+/// `(u16, u16)` and a `GridPosition`.
+impl From<(u16, u16)> for GridPosition {
+    fn from(pos: (u16, u16)) -> Self {
+        GridPosition { x: pos.0, y: pos.1 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Boundary;
+
+    #[test]
+    fn moves_wrap_at_the_edge_they_cross_on_a_wide_board() {
+        let board = Board::new(300, 5, Boundary::Wrap);
+        let corner = GridPosition::new(0, 0);
+        let up = GridPosition::new_from_move(corner, Direction::Up, &board);
+        let left = GridPosition::new_from_move(corner, Direction::Left, &board);
+        assert_eq!(up, GridPosition::new(0, 4));
+        assert_eq!(left, GridPosition::new(299, 0));
+        assert_eq!(
+            GridPosition::new_from_move(left, Direction::Right, &board),
+            corner
+        );
+        assert_eq!(
+            GridPosition::new_from_move(up, Direction::Down, &board),
+            corner
+        );
+    }
+
+    #[test]
+    fn moves_wrap_at_the_edge_they_cross_on_a_tall_board() {
+        let board = Board::new(4, 1000, Boundary::Wrap);
+        let far = GridPosition::new(3, 999);
+        let down = GridPosition::new_from_move(far, Direction::Down, &board);
+        let right = GridPosition::new_from_move(far, Direction::Right, &board);
+        assert_eq!(down, GridPosition::new(3, 0));
+        assert_eq!(right, GridPosition::new(0, 999));
+        assert_eq!(
+            GridPosition::new_from_move(down, Direction::Up, &board),
+            far
+        );
+        assert_eq!(
+            GridPosition::new_from_move(right, Direction::Left, &board),
+            far
+        );
+    }
+}
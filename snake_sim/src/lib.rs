@@ -0,0 +1,12 @@
+//! The game of snake itself: the board, the snakes and the food on it, and
+//! the rules that move them along a tick at a time. There is no window here,
+//! so bots, replays and training can play as fast as they like, and the game
+//! draws it all on top.
+
+pub mod board;
+pub mod direction;
+pub mod environment;
+pub mod food;
+pub mod grid_position;
+pub mod simulation;
+pub mod snake;
@@ -0,0 +1,450 @@
+use crate::board::Board;
+use crate::direction::Direction;
+use crate::food::{Ate, Food};
+use crate::grid_position::GridPosition;
+use crate::snake::Snake;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// The result of advancing the `Simulation` by a single tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The snake moved one cell without eating anything.
+    Moved,
+    /// The snake ate something. If it ate `Food` a new piece has already been
+    /// placed; if it ate `Itself`, a `Wall` or another `Snake` it is now dead.
+    Ate(Ate),
+    /// The snake ate the last piece of food there was room for: it now fills the
+    /// whole board and the player has won.
+    Won,
+    /// The game was already over, so nothing happened.
+    GameOver,
+}
+
+/// Everything about a game in progress that changes as it is played, so that it
+/// can be put back the way it was. The board never changes, so it is left out,
+/// which keeps taking a snapshot cheap.
+#[derive(Clone)]
+pub struct Snapshot {
+    snakes: Vec<Snake>,
+    food: Food,
+    rng: StdRng,
+    gameover: bool,
+    won: bool,
+    ticks: u64,
+    food_eaten: usize,
+}
+
+impl Snapshot {
+    /// The checksum of the game as it was when the snapshot was taken.
+    pub fn checksum(&self) -> u64 {
+        checksum(self.ticks, &self.food, &self.snakes)
+    }
+}
+
+/// The `Simulation` holds everything needed to play a game of snake: the board,
+/// the snakes, the food and the random number generator used to place food. It
+/// knows nothing about windows, input devices or drawing, so it can be stepped
+/// as fast as we like without a ggez `Context`.
+///
+/// Usually there is a single snake, but in a versus game every player has one.
+/// All the snakes move at the same time, and a snake that dies stays on the
+/// board for the others to run into until the game is over.
+pub struct Simulation {
+    board: Board,
+    snakes: Vec<Snake>,
+    food: Food,
+    rng: StdRng,
+    /// The seed `rng` was created from. Two simulations with the same board and
+    /// seed that are given the same inputs play out exactly the same way.
+    seed: u64,
+    gameover: bool,
+    /// Whether the game ended because the snakes filled the board.
+    won: bool,
+    /// How many times the game has been stepped.
+    ticks: u64,
+    /// How many pieces of food the snakes have eaten between them.
+    food_eaten: usize,
+}
+
+impl Simulation {
+    /// Set up a fresh game for a single snake on the given `board`, placing food
+    /// with a random number generator seeded from `seed`.
+    pub fn new(board: Board, seed: u64) -> Self {
+        Simulation::with_players(board, 1, seed)
+    }
+
+    /// Set up a fresh game with a snake for each of `players` on the given `board`.
+    pub fn with_players(board: Board, players: usize, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let snakes: Vec<Snake> = (0..players.max(1))
+            .map(|player| {
+                let (pos, dir) = board.spawn(player);
+                Snake::new(pos, dir, &board)
+            })
+            .collect();
+        let food = Food::new(
+            board
+                .random_free_position(&mut rng, &snakes)
+                .expect("The board is too small to fit both the snakes and their food"),
+        );
+
+        Simulation {
+            board,
+            snakes,
+            food,
+            rng,
+            seed,
+            gameover: false,
+            won: false,
+            ticks: 0,
+            food_eaten: 0,
+        }
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// The first player's snake, which is the only one outside of versus games.
+    pub fn snake(&self) -> &Snake {
+        &self.snakes[0]
+    }
+
+    pub fn snakes(&self) -> &[Snake] {
+        &self.snakes
+    }
+
+    pub fn players(&self) -> usize {
+        self.snakes.len()
+    }
+
+    pub fn food(&self) -> &Food {
+        &self.food
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.gameover
+    }
+
+    pub fn has_won(&self) -> bool {
+        self.won
+    }
+
+    /// How many ticks have been simulated since the game started.
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn food_eaten(&self) -> usize {
+        self.food_eaten
+    }
+
+    /// The score is simply the length of the snake's body, not counting its head.
+    pub fn score(&self) -> usize {
+        self.score_of(0)
+    }
+
+    /// The score of the snake belonging to `player`.
+    pub fn score_of(&self, player: usize) -> usize {
+        self.snakes[player].body.len()
+    }
+
+    /// In a versus game, the last snake left alive once the game is over. If the
+    /// last snakes died on the same tick nobody wins.
+    pub fn winner(&self) -> Option<usize> {
+        if !self.gameover || self.snakes.len() < 2 {
+            return None;
+        }
+        let mut alive = (0..self.snakes.len()).filter(|&player| self.snakes[player].is_alive());
+        match (alive.next(), alive.next()) {
+            (Some(player), None) => Some(player),
+            _ => None,
+        }
+    }
+
+    /// Take a snapshot of the game as it stands, snakes' queued up turns and the
+    /// random number generator included, to `restore` later.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            snakes: self.snakes.clone(),
+            food: self.food.clone(),
+            rng: self.rng.clone(),
+            gameover: self.gameover,
+            won: self.won,
+            ticks: self.ticks,
+            food_eaten: self.food_eaten,
+        }
+    }
+
+    /// Put the game back exactly the way it was when `snapshot` was taken.
+    /// Stepping it from there with the same inputs plays out the same way again.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.snakes = snapshot.snakes.clone();
+        self.food = snapshot.food.clone();
+        self.rng = snapshot.rng.clone();
+        self.gameover = snapshot.gameover;
+        self.won = snapshot.won;
+        self.ticks = snapshot.ticks;
+        self.food_eaten = snapshot.food_eaten;
+    }
+
+    /// A fingerprint of the state of the game: where every snake is, which way
+    /// it is going and about to turn, whether it is alive, and where the food
+    /// is. Two simulations that have played out the same way have the same
+    /// checksum, on any machine, so comparing them is a cheap way to notice
+    /// they've drifted apart.
+    pub fn checksum(&self) -> u64 {
+        checksum(self.ticks, &self.food, &self.snakes)
+    }
+
+    /// Ask the first player's snake to turn towards `dir` before the next tick.
+    /// Several calls may be made between ticks; see `Snake::steer` for how they
+    /// queue up.
+    pub fn steer(&mut self, dir: Direction) {
+        self.steer_player(0, dir);
+    }
+
+    /// Ask the snake of `player` to turn towards `dir` before the next tick.
+    pub fn steer_player(&mut self, player: usize, dir: Direction) {
+        if !self.gameover {
+            if let Some(snake) = self.snakes.get_mut(player) {
+                snake.steer(dir);
+            }
+        }
+    }
+
+    /// Advance the game by one tick, optionally steering the first snake first.
+    pub fn step(&mut self, input: Option<Direction>) -> StepOutcome {
+        self.step_players(&[input])
+    }
+
+    /// Advance the game by one tick, first steering each player's snake with
+    /// their entry in `inputs`, if they have one. The outcome is the first
+    /// player's.
+    pub fn step_players(&mut self, inputs: &[Option<Direction>]) -> StepOutcome {
+        if self.gameover {
+            return StepOutcome::GameOver;
+        }
+        for (player, input) in inputs.iter().enumerate() {
+            if let Some(dir) = *input {
+                self.steer_player(player, dir);
+            }
+        }
+
+        // First we tell every living snake to update itself, passing in a reference
+        // to our piece of food, so that they all move at once.
+        let alive: Vec<bool> = self.snakes.iter().map(Snake::is_alive).collect();
+        for (snake, &alive) in self.snakes.iter_mut().zip(&alive) {
+            if alive {
+                snake.update(&self.food, &self.board);
+            }
+        }
+        self.ticks += 1;
+
+        // Whoever reached the food ate it, before we find out whether they
+        // crashed doing so.
+        let ate_food = self
+            .snakes
+            .iter()
+            .zip(&alive)
+            .any(|(snake, &a)| a && snake.ate == Some(Ate::Food));
+
+        // Then we check whether any of them ran into another. A head that lands on
+        // another snake's body kills it, and two heads that meet kill both. We find
+        // every collision before marking anyone dead so the order doesn't matter.
+        let crashed: Vec<usize> = (0..self.snakes.len())
+            .filter(|&player| alive[player] && self.snakes[player].is_alive())
+            .filter(|&player| {
+                let head = self.snakes[player].head.pos;
+                self.snakes
+                    .iter()
+                    .enumerate()
+                    .any(|(other, snake)| other != player && snake.covers(head))
+            })
+            .collect();
+        for player in crashed {
+            self.snakes[player].ate = Some(Ate::Snake);
+        }
+
+        // If a snake ate the piece of food, we randomly select a new position for it
+        // among the cells no snake is covering and move it there. If there are none
+        // left the snakes fill the board and the game is won. A snake that died
+        // on the food still ate it, so it has to move even then.
+        if ate_food {
+            self.food_eaten += 1;
+            match self.board.random_free_position(&mut self.rng, &self.snakes) {
+                Some(pos) => self.food.pos = pos,
+                None => {
+                    self.gameover = true;
+                    self.won = true;
+                }
+            }
+        }
+
+        // A lone snake's game is over as soon as it dies. In a versus game it's
+        // over once there is at most one snake left.
+        let living = self.snakes.iter().filter(|snake| snake.is_alive()).count();
+        if living == 0 || (self.snakes.len() > 1 && living == 1) {
+            self.gameover = true;
+        }
+
+        match self.snakes[0].ate {
+            Some(Ate::Food) if self.won => StepOutcome::Won,
+            _ if !alive[0] => StepOutcome::GameOver,
+            Some(ate) => StepOutcome::Ate(ate),
+            None => StepOutcome::Moved,
+        }
+    }
+}
+
+fn checksum(ticks: u64, food: &Food, snakes: &[Snake]) -> u64 {
+    // FNV-1a, which unlike the standard library's hasher is the same everywhere.
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut add = |byte: u8| {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    };
+    for byte in ticks.to_le_bytes().iter() {
+        add(*byte);
+    }
+    for byte in position_bytes(food.pos).iter() {
+        add(*byte);
+    }
+    for snake in snakes {
+        add(snake.dir as u8);
+        add(snake.buffered_dir.map_or(4, |dir| dir as u8));
+        add(snake.is_alive() as u8);
+        for pos in snake.positions() {
+            for byte in position_bytes(pos).iter() {
+                add(*byte);
+            }
+        }
+        // Mark the end of each snake, so that where one ends and the next
+        // begins counts too.
+        add(0xff);
+    }
+    hash
+}
+
+/// The bytes of `pos` for the checksum, the same on every machine.
+fn position_bytes(pos: GridPosition) -> [u8; 4] {
+    let (x, y) = (pos.x.to_le_bytes(), pos.y.to_le_bytes());
+    [x[0], x[1], y[0], y[1]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Boundary;
+
+    /// A lone snake on an 8 by 8 board, starting at (2, 4) and moving right,
+    /// with the food put out of its way.
+    fn simulation() -> Simulation {
+        let mut simulation = Simulation::new(Board::new(8, 8, Boundary::Wrap), 7);
+        simulation.food.pos = GridPosition::new(6, 0);
+        simulation
+    }
+
+    #[test]
+    fn step_moves_the_snake_one_cell() {
+        let mut simulation = simulation();
+        assert_eq!(simulation.snake().head.pos, GridPosition::new(2, 4));
+        assert_eq!(simulation.step(None), StepOutcome::Moved);
+        assert_eq!(simulation.snake().head.pos, GridPosition::new(3, 4));
+        assert_eq!(simulation.snake().len(), 2);
+        assert_eq!(simulation.step(Some(Direction::Up)), StepOutcome::Moved);
+        assert_eq!(simulation.snake().head.pos, GridPosition::new(3, 3));
+        assert_eq!(simulation.ticks(), 2);
+    }
+
+    #[test]
+    fn eating_grows_the_snake_and_moves_the_food() {
+        let mut simulation = simulation();
+        simulation.food.pos = GridPosition::new(3, 4);
+        assert_eq!(simulation.step(None), StepOutcome::Ate(Ate::Food));
+        assert_eq!(simulation.snake().len(), 3);
+        assert_eq!(simulation.score(), 2);
+        assert_eq!(simulation.food_eaten(), 1);
+        assert!(!simulation.snake().covers(simulation.food().pos));
+    }
+
+    #[test]
+    fn running_into_itself_ends_the_game() {
+        let mut simulation = simulation();
+        for x in 3..6 {
+            simulation.food.pos = GridPosition::new(x, 4);
+            simulation.step(None);
+        }
+        simulation.food.pos = GridPosition::new(6, 0);
+        assert_eq!(simulation.snake().len(), 5);
+        simulation.step(Some(Direction::Up));
+        simulation.step(Some(Direction::Left));
+        assert_eq!(
+            simulation.step(Some(Direction::Down)),
+            StepOutcome::Ate(Ate::Itself)
+        );
+        assert!(simulation.is_over());
+        assert_eq!(simulation.step(None), StepOutcome::GameOver);
+    }
+
+    #[test]
+    fn the_snake_wraps_around_the_edges() {
+        let mut simulation = simulation();
+        for _ in 0..6 {
+            simulation.step(None);
+        }
+        assert_eq!(simulation.snake().head.pos, GridPosition::new(0, 4));
+        simulation.step(Some(Direction::Up));
+        for _ in 0..3 {
+            simulation.step(None);
+        }
+        assert_eq!(simulation.snake().head.pos, GridPosition::new(0, 0));
+        simulation.step(None);
+        assert_eq!(simulation.snake().head.pos, GridPosition::new(0, 7));
+        assert!(!simulation.is_over());
+    }
+
+    #[test]
+    fn food_eaten_by_snakes_that_crash_is_placed_again() {
+        let board = Board::new(8, 8, Boundary::Wrap);
+        let mut simulation = Simulation::with_players(board.clone(), 4, 7);
+        // The first two meet head on over the food, and the others carry on.
+        simulation.snakes = vec![
+            Snake::new(GridPosition::new(2, 4), Direction::Right, &board),
+            Snake::new(GridPosition::new(4, 4), Direction::Left, &board),
+            Snake::new(GridPosition::new(6, 1), Direction::Down, &board),
+            Snake::new(GridPosition::new(1, 1), Direction::Down, &board),
+        ];
+        simulation.food.pos = GridPosition::new(3, 4);
+        simulation.step(None);
+        assert!(!simulation.snakes()[0].is_alive());
+        assert!(!simulation.snakes()[1].is_alive());
+        assert!(!simulation.is_over());
+        assert_eq!(simulation.food_eaten(), 1);
+        assert_ne!(simulation.food().pos, GridPosition::new(3, 4));
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_play_out_the_same() {
+        let inputs = [
+            None,
+            Some(Direction::Down),
+            None,
+            Some(Direction::Left),
+            None,
+        ];
+        let play = || {
+            let mut simulation = Simulation::new(Board::new(8, 8, Boundary::Wrap), 42);
+            for &input in inputs.iter().cycle().take(40) {
+                simulation.step(input);
+            }
+            simulation.checksum()
+        };
+        assert_eq!(play(), play());
+    }
+}
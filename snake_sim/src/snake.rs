@@ -1,13 +1,12 @@
-use crate::components::direction::*;
-use crate::components::draw::draw_rect;
-use crate::components::food::*;
-use crate::components::grid_position::GridPosition;
-use crate::SNAKE_CONFIG;
-use ggez::{Context, GameResult};
+use crate::board::Board;
+use crate::direction::*;
+use crate::food::*;
+use crate::grid_position::GridPosition;
 use std::collections::LinkedList;
 
 /// Now we make a struct that contains all the information needed to describe the
 /// state of the Snake itself.
+#[derive(Clone)]
 pub struct Snake {
     /// First we have the head of the snake, which is a single `Segment`.
     pub head: Segment,
@@ -19,7 +18,7 @@ pub struct Snake {
     pub body: LinkedList<Segment>,
     /// Now we have a property that represents the result of the last update
     /// that was performed. The snake could have eaten nothing (None), Food (Some(Ate::Food)),
-    /// or Itself (Some(Ate::Itself))
+    /// Itself (Some(Ate::Itself)), a wall or another snake
     pub ate: Option<Ate>,
     /// Finally we store the direction that the snake was traveling the last
     /// time that `update` was called, which we will use to determine valid
@@ -32,30 +31,55 @@ pub struct Snake {
 }
 
 impl Snake {
-    pub fn new(pos: GridPosition) -> Self {
+    pub fn new(pos: GridPosition, dir: Direction, board: &Board) -> Self {
         let mut body = LinkedList::new();
-        // Our snake will initially have a head and one body segment,
-        // and will be moving to the right.
-        body.push_back(Segment::new((pos.x - 1, pos.y).into()));
+        // Our snake will initially have a head and one body segment just behind it,
+        // and will be moving in the direction `dir`.
+        body.push_back(Segment::new(GridPosition::new_from_move(
+            pos,
+            dir.inverse(),
+            board,
+        )));
         Snake {
             head: Segment::new(pos),
-            dir: Direction::Right,
-            last_update_dir: Direction::Right,
-            body: body,
+            dir,
+            last_update_dir: dir,
+            body,
             ate: None,
             buffered_dir: None,
         }
     }
 
+    /// The number of cells the snake covers, head included.
+    pub fn len(&self) -> usize {
+        self.body.len() + 1
+    }
+
+    /// A snake always has its head, so it is never empty.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Every position the snake covers, starting at its head.
+    pub fn positions(&self) -> impl Iterator<Item = GridPosition> + '_ {
+        std::iter::once(self.head.pos).chain(self.body.iter().map(|seg| seg.pos))
+    }
+
+    /// Whether any part of the snake is on `pos`.
+    pub fn covers(&self, pos: GridPosition) -> bool {
+        self.positions().any(|p| p == pos)
+    }
+
+    /// Whether the snake is still going, which it is until it eats something fatal.
+    pub fn is_alive(&self) -> bool {
+        !self.ate.is_some_and(|ate| ate.is_fatal())
+    }
+
     /// A helper function that determines whether
     /// the snake eats a given piece of Food based
     /// on its current position
     pub fn eats(&self, food: &Food) -> bool {
-        if self.head.pos == food.pos {
-            true
-        } else {
-            false
-        }
+        self.head.pos == food.pos
     }
 
     /// A helper function that determines whether
@@ -64,9 +88,23 @@ impl Snake {
         self.body.iter().any(|&seg| seg.pos == self.head.pos)
     }
 
+    /// Try to turn the snake towards `dir`. We check if a new direction has
+    /// already been set and make sure the new direction is different than `dir`,
+    /// in which case the turn is queued up in `buffered_dir`. Otherwise, if the
+    /// direction is not the inverse of the `last_update_dir`, the snake's new
+    /// direction becomes the direction requested.
+    pub fn steer(&mut self, dir: Direction) {
+        if self.dir != self.last_update_dir && dir.inverse() != self.dir {
+            self.buffered_dir = Some(dir);
+        } else if dir.inverse() != self.last_update_dir {
+            self.dir = dir;
+        }
+    }
+
     /// The main update function for our snake which gets called every time
-    /// we want to update the game state.
-    pub fn update(&mut self, food: &Food) {
+    /// we want to update the game state. The `board` tells us how big the
+    /// world is so we know where to wrap around, or where the walls are.
+    pub fn update(&mut self, food: &Food, board: &Board) {
         // If `last_update_dir` has already been updated to be the same as `dir`
         // and we have a `next_dir`, then set `dir` to `next_dir` and unset `next_dir`
 
@@ -74,10 +112,18 @@ impl Snake {
             self.dir = self.buffered_dir.unwrap();
             self.buffered_dir = None;
         }
-        // First we get a new head position by using our `new_from_move` helper
-        // function from earlier. We move our head in the direction we are currently
-        // heading.
-        let new_head_pos = GridPosition::new_from_move(self.head.pos, self.dir);
+        // First we get a new head position by stepping across the board in the
+        // direction we are currently heading. If that would take us off the edge
+        // or into one of the board's walls, the snake stays where it is, having
+        // eaten the wall.
+        let new_head_pos = match board.step(self.head.pos, self.dir) {
+            Some(pos) if !board.is_wall(pos) => pos,
+            _ => {
+                self.ate = Some(Ate::Wall);
+                self.last_update_dir = self.dir;
+                return;
+            }
+        };
         // Next we create a new segment will be our new head segment using the
         // new position we just made.
         let new_head = Segment::new(new_head_pos);
@@ -99,30 +145,12 @@ impl Snake {
         // which gives the illusion that the snake is moving. In reality, all the segments stay
         // stationary, we just add a segment to the front and remove one from the back. If we eat
         // a piece of food, then we leave the last segment so that we extend our body by one.
-        if let None = self.ate {
+        if self.ate.is_none() {
             self.body.pop_back();
         }
         // And set our last_update_dir to the direction we just moved.
         self.last_update_dir = self.dir;
     }
-
-    /// Here we have the Snake draw itself. This is very similar to how we saw the Food
-    /// draw itself earlier.
-    ///
-    /// Again, note that this approach to drawing is fine for the limited scope of this
-    /// example, but larger scale games will likely need a more optimized render path
-    /// using SpriteBatch or something similar that batches draw calls.
-    pub fn draw(&self, ctx: &mut Context) -> GameResult<()> {
-        // We first iterate through the body segments and draw them.
-        self.body
-            .iter()
-            .map(|seg| seg.pos)
-            .try_for_each(|pos| draw_rect(pos, SNAKE_CONFIG.snake_body, ctx))?;
-
-        // And then we do the same for the head, instead making it fully red to distinguish it.
-        draw_rect(self.head.pos, SNAKE_CONFIG.snake_head, ctx)?;
-        Ok(())
-    }
 }
 
 /// This is mostly just a semantic abstraction over a `GridPosition` to represent
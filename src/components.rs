@@ -1,6 +1,23 @@
-pub mod direction;
+pub mod battlesnake;
+pub mod campaign;
+pub mod cli;
+pub mod controller;
 pub mod draw;
-pub mod food;
+pub mod external_bot;
 pub mod game_state;
-pub mod grid_position;
-pub mod snake;
+pub mod high_scores;
+pub mod level;
+pub mod live_config;
+pub mod lockstep;
+pub mod motion;
+pub mod netcode;
+pub mod replay;
+pub mod rollback;
+pub mod skin;
+pub mod spectator;
+pub mod terminal;
+pub mod validation;
+
+// The game itself lives in `snake_sim`, which knows nothing about ggez, and is
+// reexported here so the rest of the game can keep reaching it the same way.
+pub use snake_sim::{board, direction, environment, grid_position, simulation, snake};
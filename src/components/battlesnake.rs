@@ -0,0 +1,480 @@
+use crate::components::board::{Board, Boundary};
+use crate::components::direction::Direction;
+use crate::components::grid_position::GridPosition;
+use crate::components::replay::Replay;
+use crate::components::simulation::Simulation;
+use crate::components::snake::Snake;
+use crate::components::spectator::Spectators;
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The JSON body of every request we make to a Battlesnake bot, shaped the way
+/// the Battlesnake API describes it.
+#[derive(Serialize)]
+struct GameRequest<'a> {
+    game: &'a Game,
+    turn: u64,
+    board: BattleBoard,
+    you: BattleSnake,
+}
+
+#[derive(Serialize)]
+struct Game {
+    id: String,
+    ruleset: Ruleset,
+    map: &'static str,
+    /// How long a bot has to answer, in milliseconds.
+    timeout: u64,
+    source: &'static str,
+}
+
+#[derive(Serialize)]
+struct Ruleset {
+    name: &'static str,
+    version: &'static str,
+    settings: Settings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Settings {
+    food_spawn_chance: u32,
+    minimum_food: u32,
+    hazard_damage_per_turn: u32,
+}
+
+#[derive(Serialize)]
+struct BattleBoard {
+    width: u16,
+    height: u16,
+    food: Vec<Coord>,
+    /// Walls and the snakes that have died. Unlike Battlesnake hazards, running
+    /// into either of them is fatal under our rules.
+    hazards: Vec<Coord>,
+    /// The snakes still alive.
+    snakes: Vec<BattleSnake>,
+}
+
+#[derive(Serialize)]
+struct BattleSnake {
+    id: String,
+    name: String,
+    /// Snakes don't go hungry under our rules, so this is always 100.
+    health: u32,
+    /// Every cell of the snake from the head to the tail.
+    body: Vec<Coord>,
+    /// How long the snake took to answer the last move, in milliseconds.
+    latency: String,
+    head: Coord,
+    length: usize,
+    shout: String,
+    squad: String,
+}
+
+/// A cell in Battlesnake coordinates, where `y` counts up from the bottom of the
+/// board rather than down from the top like a `GridPosition`.
+#[derive(Serialize)]
+struct Coord {
+    x: u16,
+    y: u16,
+}
+
+/// What a bot answers to a move request.
+#[derive(Deserialize)]
+struct MoveResponse {
+    #[serde(rename = "move")]
+    dir: String,
+    #[serde(default)]
+    shout: String,
+}
+
+/// A bot taking part in the game, which lives behind `url`.
+struct RemoteSnake {
+    url: String,
+    /// How long it took to answer the last move.
+    latency: Duration,
+    /// What it last shouted, passed on to everyone in the next request.
+    shout: String,
+}
+
+/// A game engine for bots written for the Battlesnake API. It plays a game by
+/// this game's rules, calling every bot's `/start` endpoint before the first
+/// turn, `/move` on every turn and `/end` once the game is over, all over
+/// HTTP with Battlesnake-shaped JSON.
+///
+/// Every turn all the bots are asked for their move at once and each gets
+/// `timeout` to answer. A bot that takes longer, answers with something we
+/// can't read, or can't be reached, keeps going the way it was going, just as
+/// it would on the Battlesnake servers.
+pub struct Engine {
+    board: Board,
+    snakes: Vec<RemoteSnake>,
+    timeout: Duration,
+    agent: ureq::Agent,
+}
+
+impl Engine {
+    /// An engine for a game on `board` between the bots at `urls`, one snake
+    /// each. There can't be more bots than the board has spawns.
+    pub fn new(board: Board, urls: Vec<String>, timeout: Duration) -> Result<Self, String> {
+        if urls.is_empty() {
+            return Err(String::from("A game needs at least one Battlesnake bot"));
+        }
+        if urls.len() > board.spawns.len() {
+            return Err(format!(
+                "The board has room for {} snakes, but {} bots were given",
+                board.spawns.len(),
+                urls.len()
+            ));
+        }
+        Ok(Engine {
+            board,
+            snakes: urls
+                .into_iter()
+                .map(|url| RemoteSnake {
+                    url: url.trim_end_matches('/').to_string(),
+                    latency: Duration::from_millis(0),
+                    shout: String::new(),
+                })
+                .collect(),
+            timeout,
+            agent: ureq::AgentBuilder::new().timeout(timeout).build(),
+        })
+    }
+
+    /// Play a whole game seeded with `seed`, stopping early after `max_ticks`,
+    /// and record every move in `replay`. Gives back the game as it ended.
+    /// Anyone watching through `spectators` sees every turn as it is played.
+    pub fn play(
+        &mut self,
+        seed: u64,
+        max_ticks: u64,
+        replay: &mut Replay,
+        mut spectators: Option<&mut Spectators>,
+    ) -> Simulation {
+        let mut simulation = Simulation::with_players(self.board.clone(), self.snakes.len(), seed);
+        let game = Game {
+            id: format!("snake-{}", seed),
+            ruleset: Ruleset {
+                name: match self.board.boundary {
+                    Boundary::Wrap => "wrapped",
+                    Boundary::Walls => "standard",
+                },
+                version: "v1.0.0",
+                settings: Settings {
+                    food_spawn_chance: 0,
+                    minimum_food: 1,
+                    hazard_damage_per_turn: 0,
+                },
+            },
+            map: "standard",
+            timeout: self.timeout.as_millis() as u64,
+            source: "custom",
+        };
+
+        let everyone: Vec<usize> = (0..self.snakes.len()).collect();
+        self.call_all(&game, &simulation, &everyone, "start");
+        while !simulation.is_over() && simulation.ticks() < max_ticks {
+            let alive: Vec<usize> = everyone
+                .iter()
+                .copied()
+                .filter(|&player| simulation.snakes()[player].is_alive())
+                .collect();
+            let answers = self.call_all(&game, &simulation, &alive, "move");
+            for (&player, answer) in alive.iter().zip(answers) {
+                if let Some(dir) = answer.and_then(|body| self.read_move(player, &body)) {
+                    replay.record(&simulation, player, dir);
+                    simulation.steer_player(player, dir);
+                }
+            }
+            simulation.step(None);
+            if let Some(spectators) = &mut spectators {
+                spectators.update(&simulation);
+            }
+        }
+        self.call_all(&game, &simulation, &everyone, "end");
+        simulation
+    }
+
+    /// Make a request to `endpoint` of every bot in `players` at the same time,
+    /// and wait for them all to answer or run out of time. Gives back the body
+    /// of each answer, or `None` for those that didn't give one.
+    fn call_all(
+        &mut self,
+        game: &Game,
+        simulation: &Simulation,
+        players: &[usize],
+        endpoint: &str,
+    ) -> Vec<Option<String>> {
+        let requests: Vec<(String, String)> = players
+            .iter()
+            .map(|&player| {
+                let request = GameRequest {
+                    game,
+                    turn: simulation.ticks(),
+                    board: self.battle_board(simulation),
+                    you: self.battle_snake(simulation, player),
+                };
+                let body = serde_json::to_string(&request)
+                    .expect("The game state can always be written as JSON");
+                (format!("{}/{}", self.snakes[player].url, endpoint), body)
+            })
+            .collect();
+
+        let agent = &self.agent;
+        let answers: Vec<(Duration, Result<String, String>)> = thread::scope(|scope| {
+            let calls: Vec<_> = requests
+                .iter()
+                .map(|(url, body)| {
+                    scope.spawn(move || {
+                        let started = Instant::now();
+                        let answer = agent
+                            .post(url)
+                            .set("Content-Type", "application/json")
+                            .send_string(body)
+                            .map_err(|e| e.to_string())
+                            .and_then(|response| response.into_string().map_err(|e| e.to_string()));
+                        (started.elapsed(), answer)
+                    })
+                })
+                .collect();
+            calls
+                .into_iter()
+                .map(|call| call.join().expect("A request thread panicked"))
+                .collect()
+        });
+
+        players
+            .iter()
+            .zip(answers)
+            .map(|(&player, (latency, answer))| {
+                let snake = &mut self.snakes[player];
+                if endpoint == "move" {
+                    snake.latency = latency;
+                }
+                match answer {
+                    Ok(body) => Some(body),
+                    Err(e) => {
+                        eprintln!(
+                            "Battlesnake {} didn't answer /{} on turn {}: {}",
+                            snake.url,
+                            endpoint,
+                            simulation.ticks(),
+                            e
+                        );
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Read the direction from the answer `player` gave to a move request.
+    fn read_move(&mut self, player: usize, body: &str) -> Option<Direction> {
+        let snake = &mut self.snakes[player];
+        let answer: MoveResponse = match serde_json::from_str(body) {
+            Ok(answer) => answer,
+            Err(e) => {
+                eprintln!("Battlesnake {} gave a move we can't read: {}", snake.url, e);
+                return None;
+            }
+        };
+        snake.shout = answer.shout;
+        match answer.dir.to_lowercase().as_str() {
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            "left" => Some(Direction::Left),
+            "right" => Some(Direction::Right),
+            other => {
+                eprintln!("Battlesnake {} asked to move {:?}", snake.url, other);
+                None
+            }
+        }
+    }
+
+    fn battle_board(&self, simulation: &Simulation) -> BattleBoard {
+        let snakes = simulation.snakes();
+        let mut hazards: Vec<Coord> = self
+            .board
+            .walls
+            .iter()
+            .map(|&wall| self.coord(wall))
+            .collect();
+        for snake in snakes.iter().filter(|snake| !snake.is_alive()) {
+            hazards.extend(snake.positions().map(|pos| self.coord(pos)));
+        }
+        BattleBoard {
+            width: self.board.width,
+            height: self.board.height,
+            food: vec![self.coord(simulation.food().pos)],
+            hazards,
+            snakes: (0..snakes.len())
+                .filter(|&player| snakes[player].is_alive())
+                .map(|player| self.battle_snake(simulation, player))
+                .collect(),
+        }
+    }
+
+    fn battle_snake(&self, simulation: &Simulation, player: usize) -> BattleSnake {
+        let snake: &Snake = &simulation.snakes()[player];
+        let remote = &self.snakes[player];
+        BattleSnake {
+            id: format!("player-{}", player + 1),
+            name: format!("Player {}", player + 1),
+            health: 100,
+            body: snake.positions().map(|pos| self.coord(pos)).collect(),
+            latency: remote.latency.as_millis().to_string(),
+            head: self.coord(snake.head.pos),
+            length: snake.len(),
+            shout: remote.shout.clone(),
+            squad: String::new(),
+        }
+    }
+
+    /// Turn a `GridPosition` into Battlesnake coordinates.
+    fn coord(&self, pos: GridPosition) -> Coord {
+        Coord {
+            x: pos.x,
+            y: self.board.height - 1 - pos.y,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    /// A Battlesnake bot on localhost that answers every request with whatever
+    /// `answer` gives for its endpoint and turn, after the delay it gives. Every
+    /// endpoint called is noted down in `calls`, along with the turn.
+    struct StubBot {
+        url: String,
+        calls: Arc<Mutex<Vec<(String, u64)>>>,
+    }
+
+    impl StubBot {
+        fn serve(answer: fn(&str, u64) -> (Duration, String)) -> Self {
+            let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let calls = Arc::new(Mutex::new(Vec::new()));
+            let noted = Arc::clone(&calls);
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let noted = Arc::clone(&noted);
+                    thread::spawn(move || StubBot::answer(stream, answer, &noted));
+                }
+            });
+            StubBot { url, calls }
+        }
+
+        fn answer(
+            stream: TcpStream,
+            answer: fn(&str, u64) -> (Duration, String),
+            calls: &Mutex<Vec<(String, u64)>>,
+        ) {
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let endpoint = line.split_whitespace().nth(1).unwrap().to_string();
+            let mut length = 0;
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            let turn = request["turn"].as_u64().unwrap();
+            assert_eq!(request["you"]["id"], "player-1");
+            calls.lock().unwrap().push((endpoint.clone(), turn));
+
+            let (delay, body) = answer(&endpoint, turn);
+            thread::sleep(delay);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            // A bot that took too long finds nobody waiting for its answer.
+            let _ = reader.get_mut().write_all(response.as_bytes());
+        }
+
+        fn calls(&self) -> Vec<(String, u64)> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    /// A lone snake on a small walled board, starting at (2, 4) heading right.
+    fn board() -> Board {
+        Board::new(8, 8, Boundary::Walls)
+    }
+
+    fn play(bot: &StubBot, timeout: Duration) -> Simulation {
+        let mut engine = Engine::new(board(), vec![bot.url.clone()], timeout).unwrap();
+        let mut replay = Replay::new(1, crate::default_config(), None, 1);
+        engine.play(1, 100, &mut replay, None)
+    }
+
+    /// How the game goes if the snake is never steered at all.
+    fn straight_ahead() -> Simulation {
+        let mut simulation = Simulation::new(board(), 1);
+        while !simulation.is_over() {
+            simulation.step(None);
+        }
+        simulation
+    }
+
+    #[test]
+    fn a_bot_is_started_asked_every_move_and_told_the_game_ended() {
+        let bot = StubBot::serve(|_, _| {
+            let body = String::from(r#"{"move": "up", "shout": "Onwards!"}"#);
+            (Duration::from_millis(0), body)
+        });
+        let simulation = play(&bot, Duration::from_secs(5));
+
+        // Up from (2, 4) takes the snake into the top wall on the fifth move.
+        assert_eq!(simulation.ticks(), 5);
+        let mut expected = vec![(String::from("/start"), 0)];
+        expected.extend((0..5).map(|turn| (String::from("/move"), turn)));
+        expected.push((String::from("/end"), 5));
+        assert_eq!(bot.calls(), expected);
+    }
+
+    #[test]
+    fn a_bot_that_answers_too_late_keeps_going_straight() {
+        let bot = StubBot::serve(|endpoint, _| {
+            let delay = if endpoint == "/move" { 500 } else { 0 };
+            let body = String::from(r#"{"move": "up"}"#);
+            (Duration::from_millis(delay), body)
+        });
+        let simulation = play(&bot, Duration::from_millis(100));
+        assert_eq!(simulation.checksum(), straight_ahead().checksum());
+    }
+
+    #[test]
+    fn a_move_we_cant_read_keeps_the_snake_going_straight() {
+        let bot = StubBot::serve(|_, turn| {
+            let body = if turn % 2 == 0 {
+                String::from("up, please")
+            } else {
+                String::from(r#"{"move": "sideways"}"#)
+            };
+            (Duration::from_millis(0), body)
+        });
+        let simulation = play(&bot, Duration::from_secs(5));
+        assert_eq!(simulation.checksum(), straight_ahead().checksum());
+        assert_eq!(bot.calls().last().unwrap().0, "/end");
+    }
+}
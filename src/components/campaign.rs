@@ -0,0 +1,221 @@
+use crate::components::level::Level;
+use crate::components::simulation::Simulation;
+use crate::{data_file, SnakeConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// What the player has to do to finish a stage of a campaign.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Goal {
+    /// Grow the snake to this length, head included.
+    Length(usize),
+    /// Eat this many pieces of food.
+    Food(usize),
+}
+
+impl Goal {
+    /// How long the snake is when a stage starts, head included.
+    const START_LENGTH: usize = 2;
+
+    pub fn reached(&self, simulation: &Simulation) -> bool {
+        match *self {
+            Goal::Length(length) => simulation.snake().len() >= length,
+            Goal::Food(food) => simulation.food_eaten() >= food,
+        }
+    }
+
+    /// Make sure the goal can be reached, and isn't already reached when the
+    /// stage starts, on a board with `free` cells that aren't walls.
+    pub fn check(&self, free: usize) -> Result<(), String> {
+        match *self {
+            Goal::Length(length) if length <= Goal::START_LENGTH => Err(format!(
+                "`length` must be more than the {} the snake starts at",
+                Goal::START_LENGTH
+            )),
+            Goal::Length(length) if length > free => Err(format!(
+                "`length` can't be more than the {} free cells of the board",
+                free
+            )),
+            Goal::Food(0) => Err(String::from("`food` must be at least 1")),
+            // Every piece of food eaten makes the snake one longer.
+            Goal::Food(food) if food + Goal::START_LENGTH > free => Err(format!(
+                "`food` can't be more than the {} the board has room for",
+                free.saturating_sub(Goal::START_LENGTH)
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match *self {
+            Goal::Length(length) => format!("Grow to Length {}", length),
+            Goal::Food(food) => format!("Eat {} Food", food),
+        }
+    }
+}
+
+/// One level of a campaign, along with its goal.
+#[derive(Clone, Debug)]
+pub struct Stage {
+    /// The level to play, or `None` for an empty board.
+    pub level: Option<Level>,
+    pub goal: Goal,
+}
+
+impl Stage {
+    pub fn name(&self) -> &str {
+        self.level
+            .as_ref()
+            .map_or("Open Field", |level| &level.name)
+    }
+}
+
+/// A stage as it is written in a campaign file.
+#[derive(Deserialize)]
+struct StageFile {
+    /// The path to the level file, relative to the campaign file.
+    level: Option<String>,
+    length: Option<usize>,
+    food: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct CampaignFile {
+    name: String,
+    stages: Vec<StageFile>,
+}
+
+/// A `Campaign` is an ordered list of stages to be played one after another.
+/// It is written as a TOML file like so:
+///
+/// ```toml
+/// name = "Tutorial"
+///
+/// [[stages]]
+/// food = 5
+///
+/// [[stages]]
+/// level = "levels/box.toml"
+/// length = 15
+/// ```
+///
+/// where every stage has either a `length` to grow to or a number of `food` to
+/// eat, and optionally a `level` to play on.
+#[derive(Clone, Debug)]
+pub struct Campaign {
+    pub name: String,
+    pub stages: Vec<Stage>,
+}
+
+impl Campaign {
+    /// Read a campaign from `path`, loading every level in it and checking that
+    /// they can be played with `config`.
+    pub fn load(path: &Path, config: &SnakeConfig) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let file: CampaignFile = toml::from_str(&text).map_err(|e| e.to_string())?;
+        if file.stages.is_empty() {
+            return Err(String::from("The campaign has no stages"));
+        }
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut stages = Vec::new();
+        for (i, stage) in file.stages.into_iter().enumerate() {
+            let goal = match (stage.length, stage.food) {
+                (Some(length), None) => Goal::Length(length),
+                (None, Some(food)) => Goal::Food(food),
+                _ => {
+                    return Err(format!(
+                        "Stage {} needs exactly one of `length` or `food`",
+                        i + 1
+                    ))
+                }
+            };
+            let level = match stage.level {
+                Some(level_path) => Some(
+                    Level::load(&dir.join(&level_path), config)
+                        .map_err(|e| format!("Stage {} level {}: {}", i + 1, level_path, e))?,
+                ),
+                None => None,
+            };
+            let board = config
+                .board(level.as_ref())
+                .map_err(|e| format!("Stage {}: {}", i + 1, e))?;
+            goal.check(board.cells() - board.walls.len())
+                .map_err(|e| format!("Stage {}: {}", i + 1, e))?;
+            stages.push(Stage { level, goal });
+        }
+
+        Ok(Campaign {
+            name: file.name,
+            stages,
+        })
+    }
+}
+
+/// How many stages of each campaign the player has unlocked, kept in the user's
+/// data directory so that it survives between sessions.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Progress {
+    unlocked: HashMap<String, usize>,
+}
+
+impl Progress {
+    pub fn load() -> Self {
+        data_file("campaign_progress.json")
+            .and_then(|path| File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = data_file("campaign_progress.json").ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "No data directory to save to")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        Ok(serde_json::to_writer_pretty(BufWriter::new(file), self)?)
+    }
+
+    /// How many stages of `campaign` can be played. The first one always can.
+    pub fn unlocked(&self, campaign: &Campaign) -> usize {
+        self.unlocked
+            .get(&campaign.name)
+            .copied()
+            .unwrap_or(1)
+            .clamp(1, campaign.stages.len())
+    }
+
+    /// Let the player play the stage after `stage` from now on.
+    pub fn unlock_after(&mut self, campaign: &Campaign, stage: usize) {
+        let unlocked = (stage + 2).min(campaign.stages.len());
+        if unlocked > self.unlocked(campaign) {
+            self.unlocked.insert(campaign.name.clone(), unlocked);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goals_have_to_be_reachable() {
+        assert!(Goal::Length(2).check(100).is_err());
+        assert!(Goal::Length(3).check(100).is_ok());
+        assert!(Goal::Length(101).check(100).is_err());
+        assert!(Goal::Food(0).check(100).is_err());
+        assert!(Goal::Food(98).check(100).is_ok());
+        assert!(Goal::Food(99).check(100).is_err());
+    }
+
+    #[test]
+    fn the_example_campaign_loads() {
+        let campaign = Campaign::load(Path::new("campaign.toml"), &crate::default_config());
+        assert!(campaign.is_ok(), "{:?}", campaign.err());
+    }
+}
@@ -0,0 +1,398 @@
+use crate::components::board::Boundary;
+use crate::SnakeConfig;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// What to show for `--help`, or when the command line doesn't make sense.
+pub const USAGE: &str = "\
+Usage: snake_gg_ez [COMMAND] [OPTIONS]
+
+Commands:
+  play                Play the game, which is what happens without a command
+  replay <FILE>       Watch a replay, or with --verify check it ends the way it
+                      was recorded without opening a window
+  simulate            Play a game between bots without opening a window
+  scores              Print the high score tables
+
+Options, which take over from Config.toml and SNAKE_ environment variables:
+  --config <FILE>     Read the config from FILE instead of Config.toml
+  --width <CELLS>     How many cells wide the board is
+  --height <CELLS>    How many cells tall the board is
+  --cell-size <PX>    How big each cell is drawn, in pixels
+  --speed <TICKS>     How many times a second the snakes move
+  --seed <SEED>       Place the food the same way every time
+  --mode <MODE>       What the edge of the board is: wrap or walls
+  --fullscreen        Fill the whole screen
+  --print-config      Print the config the options add up to, and stop
+  --check-config      Report every problem with the config, and stop
+  -h, --help          Print this and stop
+
+Playing:
+  --versus            A two player match, the second player on WASD
+  --demo              Bots play until someone presses Enter
+  --terminal          Play in the terminal rather than a window
+  --level <FILE>      Play on a level
+  --campaign <FILE>   Play through a campaign of levels
+  --bot <COMMAND>     Have an external bot program steer the first snake
+  --record <DIR>      Save every game played to DIR as a replay
+  --frame-time        Show how long frames take to draw, also toggled with F3
+  --host <PORT>       Host a networked game
+  --players <N>       How many players the hosted game is for, 2 to 4
+  --join <ADDRESS>    Join a networked game at ADDRESS:PORT
+  --rollback          Play a networked game with rollback netcode
+  --latency <MS>      With --rollback, delay every packet by MS milliseconds
+  --jitter <MS>       With --rollback, delay packets by up to MS more at random
+  --loss <FRACTION>   With --rollback, lose that fraction of packets, 0 to 1
+  --spectators <PORT> Stream games to spectators on PORT
+  --watch <ADDRESS>   Watch a game being streamed at ADDRESS:PORT
+
+Simulating:
+  --headless          The same as the simulate command
+  --verify            With replay, check it ends the way it was recorded
+  --max-ticks <N>     Give up on a game after N ticks
+  --battlesnake <URL>,<URL>
+                      Play between bots written for the Battlesnake API
+  --gym <N>           Run N training environments over stdin and stdout
+  --check-autopilot   Check the autopilot fills the board
+";
+
+/// What the game was asked to do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Play the game, in a window or in the terminal.
+    Play,
+    /// Watch the replay in `path`, or if `verify` check that it ends the way it
+    /// was recorded without opening a window.
+    Replay { path: String, verify: bool },
+    /// Play a game between bots without opening a window.
+    Simulate,
+    /// Print the high score tables.
+    Scores,
+}
+
+/// Everything given on the command line: the command, the options that layer
+/// over the config, and the flags for the different ways to play.
+#[derive(Clone, Debug)]
+pub struct Cli {
+    pub command: Command,
+    /// The config file to read instead of `Config.toml`.
+    pub config: Option<PathBuf>,
+    pub width: Option<u16>,
+    pub height: Option<u16>,
+    /// The width and height of every cell.
+    pub cell_size: Option<u16>,
+    pub speed: Option<f32>,
+    pub seed: Option<u64>,
+    pub mode: Option<Boundary>,
+    pub fullscreen: bool,
+    pub print_config: bool,
+    pub check_config: bool,
+    pub help: bool,
+    pub versus: bool,
+    pub demo: bool,
+    pub terminal: bool,
+    pub level: Option<String>,
+    pub campaign: Option<String>,
+    /// The command that runs the external bot steering the first snake.
+    pub bot: Option<String>,
+    /// Where to save every game played as a replay.
+    pub record: Option<PathBuf>,
+    pub frame_time: bool,
+    /// The port to host a networked game on.
+    pub host: Option<u16>,
+    pub players: Option<usize>,
+    /// The `address:port` of the networked game to join.
+    pub join: Option<String>,
+    pub rollback: bool,
+    pub latency: Option<u64>,
+    pub jitter: Option<u64>,
+    pub loss: Option<f32>,
+    /// The port to stream games to spectators on.
+    pub spectators: Option<u16>,
+    /// The `address:port` of the game to watch.
+    pub watch: Option<String>,
+    pub max_ticks: Option<u64>,
+    /// The url of every Battlesnake bot, one for each snake.
+    pub battlesnake: Option<Vec<String>>,
+    /// How many training environments to run.
+    pub gym: Option<usize>,
+    pub check_autopilot: bool,
+}
+
+impl Cli {
+    /// Make sense of `args`, which don't include the name of the program.
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut cli = Cli {
+            command: Command::Play,
+            config: None,
+            width: None,
+            height: None,
+            cell_size: None,
+            speed: None,
+            seed: None,
+            mode: None,
+            fullscreen: false,
+            print_config: false,
+            check_config: false,
+            help: false,
+            versus: false,
+            demo: false,
+            terminal: false,
+            level: None,
+            campaign: None,
+            bot: None,
+            record: None,
+            frame_time: false,
+            host: None,
+            players: None,
+            join: None,
+            rollback: false,
+            latency: None,
+            jitter: None,
+            loss: None,
+            spectators: None,
+            watch: None,
+            max_ticks: None,
+            battlesnake: None,
+            gym: None,
+            check_autopilot: false,
+        };
+        let mut args = args.iter().peekable();
+        // A command comes first. Before commands there were flags for most of
+        // them, so those still work too.
+        let command = match args.peek().map(|arg| arg.as_str()) {
+            Some("play") => Some(Command::Play),
+            Some("replay") => {
+                args.next();
+                match args.peek() {
+                    Some(path) if !path.starts_with('-') => Some(Command::Replay {
+                        path: path.to_string(),
+                        verify: false,
+                    }),
+                    _ => return Err(String::from("replay needs a file to play back")),
+                }
+            }
+            Some("simulate") => Some(Command::Simulate),
+            Some("scores") => Some(Command::Scores),
+            Some(other) if !other.starts_with('-') => {
+                return Err(format!("There's no command called {}", other))
+            }
+            _ => None,
+        };
+        // The options follow the command, or the file for `replay`.
+        if command.is_some() {
+            args.next();
+        }
+
+        let (mut replay, mut verify_replay, mut headless, mut verify) = (None, None, false, false);
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .map(String::as_str)
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--config" => cli.config = Some(PathBuf::from(value()?)),
+                "--width" => cli.width = Some(parse(flag, value()?)?),
+                "--height" => cli.height = Some(parse(flag, value()?)?),
+                "--cell-size" => cli.cell_size = Some(parse(flag, value()?)?),
+                "--speed" => cli.speed = Some(parse(flag, value()?)?),
+                "--seed" => cli.seed = Some(parse(flag, value()?)?),
+                "--mode" => {
+                    cli.mode = match value()? {
+                        "wrap" => Some(Boundary::Wrap),
+                        "walls" => Some(Boundary::Walls),
+                        other => {
+                            return Err(format!("--mode must be wrap or walls, not {}", other))
+                        }
+                    }
+                }
+                "--fullscreen" => cli.fullscreen = true,
+                "--print-config" => cli.print_config = true,
+                "--check-config" => cli.check_config = true,
+                "-h" | "--help" => cli.help = true,
+                "--versus" => cli.versus = true,
+                "--demo" => cli.demo = true,
+                "--terminal" => cli.terminal = true,
+                "--level" => cli.level = Some(value()?.to_string()),
+                "--campaign" => cli.campaign = Some(value()?.to_string()),
+                "--bot" => cli.bot = Some(value()?.to_string()),
+                "--record" => cli.record = Some(PathBuf::from(value()?)),
+                "--frame-time" => cli.frame_time = true,
+                "--host" => cli.host = Some(parse(flag, value()?)?),
+                "--players" => cli.players = Some(parse(flag, value()?)?),
+                "--join" => cli.join = Some(value()?.to_string()),
+                "--rollback" => cli.rollback = true,
+                "--latency" => cli.latency = Some(parse(flag, value()?)?),
+                "--jitter" => cli.jitter = Some(parse(flag, value()?)?),
+                "--loss" => cli.loss = Some(parse(flag, value()?)?),
+                "--spectators" => cli.spectators = Some(parse(flag, value()?)?),
+                "--watch" => cli.watch = Some(value()?.to_string()),
+                "--max-ticks" => cli.max_ticks = Some(parse(flag, value()?)?),
+                "--battlesnake" => {
+                    cli.battlesnake = Some(value()?.split(',').map(String::from).collect())
+                }
+                "--gym" => cli.gym = Some(parse(flag, value()?)?),
+                "--check-autopilot" => cli.check_autopilot = true,
+                "--verify" => verify = true,
+                "--replay" => replay = Some(value()?.to_string()),
+                "--verify-replay" => verify_replay = Some(value()?.to_string()),
+                "--headless" => headless = true,
+                other => return Err(format!("There's no option called {}", other)),
+            }
+        }
+
+        cli.command = match (command, replay, verify_replay) {
+            (Some(command), _, _) => command,
+            (None, _, Some(path)) => Command::Replay { path, verify: true },
+            (None, Some(path), None) => Command::Replay {
+                path,
+                verify: false,
+            },
+            (None, None, None) if headless => Command::Simulate,
+            (None, None, None) => Command::Play,
+        };
+        if verify {
+            match &mut cli.command {
+                Command::Replay { verify, .. } => *verify = true,
+                _ => return Err(String::from("--verify only goes with replay")),
+            }
+        }
+        Ok(cli)
+    }
+
+    /// Lay the options given over `config`.
+    pub fn apply(&self, config: &mut SnakeConfig) {
+        if let Some(width) = self.width {
+            config.grid_width = width;
+        }
+        if let Some(height) = self.height {
+            config.grid_height = height;
+        }
+        if let Some(size) = self.cell_size {
+            config.cell_width = size;
+            config.cell_height = size;
+        }
+        if let Some(speed) = self.speed {
+            config.updates_per_second = speed;
+        }
+        if self.seed.is_some() {
+            config.seed = self.seed;
+        }
+        if let Some(mode) = self.mode {
+            config.boundary = mode;
+        }
+        if self.fullscreen {
+            config.fullscreen = true;
+        }
+        if self.level.is_some() {
+            config.level = self.level.clone();
+        }
+        if self.campaign.is_some() {
+            config.campaign = self.campaign.clone();
+        }
+    }
+}
+
+/// The `value` given for `flag`, such as `1234` for `--seed 1234`.
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{} can't be {}", flag, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(line: &str) -> Result<Cli, String> {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+        Cli::parse(&args)
+    }
+
+    #[test]
+    fn the_command_comes_first() {
+        assert_eq!(cli("").unwrap().command, Command::Play);
+        assert_eq!(cli("play --versus").unwrap().command, Command::Play);
+        assert_eq!(cli("simulate").unwrap().command, Command::Simulate);
+        assert_eq!(cli("scores").unwrap().command, Command::Scores);
+        assert_eq!(
+            cli("replay game.json --verify").unwrap().command,
+            Command::Replay {
+                path: String::from("game.json"),
+                verify: true
+            }
+        );
+        assert!(cli("replay --verify").is_err());
+        assert!(cli("replya game.json").is_err());
+    }
+
+    #[test]
+    fn the_old_flags_still_pick_the_command() {
+        assert_eq!(cli("--headless").unwrap().command, Command::Simulate);
+        assert_eq!(
+            cli("--replay game.json").unwrap().command,
+            Command::Replay {
+                path: String::from("game.json"),
+                verify: false
+            }
+        );
+        assert_eq!(
+            cli("--verify-replay game.json").unwrap().command,
+            Command::Replay {
+                path: String::from("game.json"),
+                verify: true
+            }
+        );
+        assert!(cli("simulate --verify").is_err());
+    }
+
+    #[test]
+    fn options_take_their_values() {
+        let cli = cli(
+            "simulate --width 20 --mode walls --max-ticks 500 --gym 4 --host 7777 \
+             --players 3 --loss 0.25 --battlesnake http://a,http://b --check-autopilot",
+        )
+        .unwrap();
+        assert_eq!(cli.width, Some(20));
+        assert_eq!(cli.mode, Some(Boundary::Walls));
+        assert_eq!(cli.max_ticks, Some(500));
+        assert_eq!(cli.gym, Some(4));
+        assert_eq!(cli.host, Some(7777));
+        assert_eq!(cli.players, Some(3));
+        assert_eq!(cli.loss, Some(0.25));
+        assert_eq!(
+            cli.battlesnake,
+            Some(vec![String::from("http://a"), String::from("http://b")])
+        );
+        assert!(cli.check_autopilot);
+        assert!(!cli.versus);
+    }
+
+    #[test]
+    fn options_are_laid_over_the_config() {
+        let mut config = crate::default_config();
+        cli("--width 40 --level levels/box.toml --campaign campaign.toml")
+            .unwrap()
+            .apply(&mut config);
+        assert_eq!(config.grid_width, 40);
+        assert_eq!(config.level.as_deref(), Some("levels/box.toml"));
+        assert_eq!(config.campaign.as_deref(), Some("campaign.toml"));
+    }
+
+    #[test]
+    fn nonsense_is_turned_away() {
+        assert_eq!(
+            cli("--fulscreen").unwrap_err(),
+            "There's no option called --fulscreen"
+        );
+        assert_eq!(
+            cli("--max-ticks lots").unwrap_err(),
+            "--max-ticks can't be lots"
+        );
+        assert_eq!(cli("--gym -1").unwrap_err(), "--gym can't be -1");
+        assert_eq!(cli("--seed").unwrap_err(), "--seed needs a value");
+        assert!(cli("--mode round").is_err());
+        assert!(cli("play simulate").is_err());
+    }
+}
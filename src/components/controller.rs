@@ -0,0 +1,502 @@
+use crate::components::board::Board;
+use crate::components::direction::Direction;
+use crate::components::external_bot::ExternalBot;
+use crate::components::grid_position::GridPosition;
+use crate::components::simulation::Simulation;
+use crate::SNAKE_CONFIG;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// A `Controller` plays a snake instead of a person. Every tick it is shown the
+/// game as it stands and decides which way its snake should go next.
+pub trait Controller {
+    /// Which way the snake of `player` should head on the next tick of `simulation`.
+    fn next_dir(&mut self, simulation: &Simulation, player: usize) -> Direction;
+
+    /// Whether `next_dir` can answer for the next tick of `simulation` without
+    /// keeping us waiting. A bot that thinks somewhere else, like `ExternalBot`,
+    /// starts thinking here, and the game keeps drawing frames and asks again
+    /// until it is ready. Most bots answer straight away.
+    fn is_ready(&mut self, _simulation: &Simulation, _player: usize) -> bool {
+        true
+    }
+}
+
+/// Who steers a snake, as chosen for each player in the config.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControllerKind {
+    /// A person at the keyboard.
+    #[default]
+    Human,
+    /// A bot that heads straight for the food, see `Greedy`.
+    Greedy,
+    /// A bot that plans its way to the food, see `Pathfinder`.
+    Pathfinder,
+    /// An autopilot that fills the whole board, see `Hamiltonian`.
+    Hamiltonian,
+    /// A program of your own, run with `bot_command` from the config, see `ExternalBot`.
+    External,
+}
+
+impl ControllerKind {
+    /// The bot for this kind of controller, or `None` if a person is playing.
+    /// An external bot can't be had without a `bot_command` that starts.
+    pub fn build(self) -> Result<Option<Box<dyn Controller>>, String> {
+        Ok(match self {
+            ControllerKind::Human => None,
+            ControllerKind::Greedy => Some(Box::new(Greedy)),
+            ControllerKind::Pathfinder => Some(Box::new(Pathfinder)),
+            ControllerKind::Hamiltonian => Some(Box::new(Hamiltonian::default())),
+            ControllerKind::External => {
+                let config = SNAKE_CONFIG.get();
+                let command = config
+                    .bot_command
+                    .as_deref()
+                    .ok_or("An external bot needs a `bot_command` in the config or --bot")?;
+                let timeout = Duration::from_millis(config.bot_timeout_ms);
+                let bot = ExternalBot::spawn(command, timeout)
+                    .map_err(|e| format!("Couldn't start bot `{}`: {}", command, e))?;
+                Some(Box::new(bot))
+            }
+        })
+    }
+}
+
+/// The simplest bot there is: of the moves that don't kill it right away, it
+/// takes the one that ends up closest to the food. It is easily trapped.
+pub struct Greedy;
+
+impl Controller for Greedy {
+    fn next_dir(&mut self, simulation: &Simulation, player: usize) -> Direction {
+        let view = View::new(simulation, player);
+        let food = simulation.food().pos;
+        view.safe_moves()
+            .into_iter()
+            .min_by_key(|&(_, pos)| view.board.distance(pos, food))
+            .map_or(view.dir, |(dir, _)| dir)
+    }
+}
+
+/// A bot that searches for the shortest path to the food, but only follows it
+/// if it could still reach its own tail once it got there. Chasing its tail
+/// always leaves it a way out, so that is what it does when the food isn't
+/// safe to go for, and if even its tail is out of reach it heads for whichever
+/// neighbouring cell has the most room around it.
+pub struct Pathfinder;
+
+impl Controller for Pathfinder {
+    fn next_dir(&mut self, simulation: &Simulation, player: usize) -> Direction {
+        let view = View::new(simulation, player);
+        let snake = &simulation.snakes()[player];
+        let food = simulation.food().pos;
+
+        if let Some(path) = view.path(&view.blocked, snake.head.pos, food) {
+            if view.safe_after(&path) {
+                return view.dir_to(path[0]);
+            }
+        }
+
+        let tail = snake.positions().last().unwrap_or(snake.head.pos);
+        if let Some(path) = view.path(&view.blocked, snake.head.pos, tail) {
+            if view.moves().contains(&(view.dir_to(path[0]), path[0])) {
+                return view.dir_to(path[0]);
+            }
+        }
+
+        view.safe_moves()
+            .into_iter()
+            .max_by_key(|&(_, pos)| view.room(pos))
+            .map_or(view.dir, |(dir, _)| dir)
+    }
+}
+
+/// An autopilot that can fill the whole board. It lays a Hamiltonian cycle over
+/// the board, a loop that passes through every cell exactly once, and follows it
+/// round and round. Every cell between the head and the tail along the cycle is
+/// free, so following the cycle the head can only ever catch up with the tail by
+/// eating, and once the snake is laid out along the cycle that happens just as
+/// the board fills up.
+///
+/// Following the cycle alone is slow, so while the board is less than half full
+/// the snake takes shortcuts, jumping ahead along the cycle to a neighbouring
+/// cell as long as that doesn't skip past the food or come too close to its
+/// tail. Once the board is half full it sticks to the cycle.
+///
+/// The cycle zig-zags across the board, so it needs an even number of rows or
+/// columns, and no walls in the way. Anywhere else it plays like a `Pathfinder`.
+#[derive(Default)]
+pub struct Hamiltonian {
+    /// The board the cycle was laid over, so we know when we need a new one.
+    board: Option<Board>,
+    /// Where each cell comes along the cycle, indexed with `Board::index`, or
+    /// `None` if we couldn't lay a cycle over the board.
+    order: Option<Vec<usize>>,
+    /// Whether we are following the cycle backwards this game.
+    reversed: bool,
+}
+
+impl Hamiltonian {
+    /// How close along the cycle a shortcut may bring the head to the tail.
+    const TAIL_MARGIN: usize = 3;
+
+    /// A Hamiltonian cycle over `board`, as the cells in the order it visits them.
+    ///
+    /// Starting in the top left corner it runs along the first row, back along the
+    /// second and so on, always leaving out the first column, then comes back up
+    /// the first column to where it started. That only meets up again if there's
+    /// an even number of rows, so if there isn't we do the same thing on its side.
+    pub fn cycle(board: &Board) -> Result<Vec<GridPosition>, String> {
+        if !board.walls.is_empty() {
+            return Err(String::from(
+                "The autopilot can't play on a board with walls in it",
+            ));
+        }
+        let (width, height) = (board.width, board.height);
+        if width < 2 || height < 2 {
+            return Err(String::from(
+                "The autopilot needs a board at least 2 cells wide and tall",
+            ));
+        }
+        if height % 2 == 0 {
+            Ok(zig_zag(width, height, |x, y| (x, y).into()))
+        } else if width % 2 == 0 {
+            Ok(zig_zag(height, width, |x, y| (y, x).into()))
+        } else {
+            Err(String::from(
+                "The autopilot needs a board with an even width or height",
+            ))
+        }
+    }
+
+    /// Play a whole game on `board` without drawing anything and check that the
+    /// autopilot fills it. Gives how many ticks that took, or what went wrong.
+    pub fn check(board: Board, seed: u64) -> Result<u64, String> {
+        Hamiltonian::cycle(&board)?;
+        // Going all the way round the cycle for every piece of food is as slow
+        // as it gets, so if the game goes on longer than that something is wrong.
+        let limit = (board.cells() * board.cells()) as u64;
+        let mut simulation = Simulation::new(board, seed);
+        let mut autopilot = Hamiltonian::default();
+        while !simulation.is_over() && simulation.ticks() < limit {
+            let dir = autopilot.next_dir(&simulation, 0);
+            simulation.step(Some(dir));
+        }
+
+        if simulation.has_won() {
+            Ok(simulation.ticks())
+        } else {
+            let ending = match simulation.snake().ate {
+                Some(ate) if ate.is_fatal() => format!("ate {:?}", ate),
+                _ => String::from("ran out of time"),
+            };
+            Err(format!(
+                "The autopilot {} after {} ticks with the snake {} long",
+                ending,
+                simulation.ticks(),
+                simulation.snake().len()
+            ))
+        }
+    }
+
+    /// Lay a new cycle if we're playing on a different board than last time.
+    fn lay_cycle(&mut self, board: &Board) {
+        if self.board.as_ref() == Some(board) {
+            return;
+        }
+        self.order = Hamiltonian::cycle(board).ok().map(|cycle| {
+            let mut order = vec![0; board.cells()];
+            for (i, &pos) in cycle.iter().enumerate() {
+                order[board.index(pos)] = i;
+            }
+            order
+        });
+        self.board = Some(board.clone());
+    }
+}
+
+impl Controller for Hamiltonian {
+    fn next_dir(&mut self, simulation: &Simulation, player: usize) -> Direction {
+        let board = simulation.board();
+        self.lay_cycle(board);
+        let order = match &self.order {
+            Some(order) => order,
+            None => return Pathfinder.next_dir(simulation, player),
+        };
+
+        let snake = &simulation.snakes()[player];
+        let cells = board.cells();
+        let head = order[board.index(snake.head.pos)];
+        let tail_pos = snake.positions().last().unwrap_or(snake.head.pos);
+        // At the start of a game we go whichever way round the cycle leaves the
+        // most room between our head and our tail.
+        if simulation.ticks() == 0 {
+            let ahead = (order[board.index(tail_pos)] + cells - head) % cells;
+            self.reversed = ahead < cells - ahead;
+        }
+        // How far ahead of the head `pos` is along the cycle.
+        let reversed = self.reversed;
+        let ahead = |pos: GridPosition| {
+            let at = order[board.index(pos)];
+            if reversed {
+                (head + cells - at) % cells
+            } else {
+                (at + cells - head) % cells
+            }
+        };
+
+        let view = View::new(simulation, player);
+        let free = view.blocked.iter().filter(|&&blocked| !blocked).count();
+        let max_jump = if free * 2 < cells {
+            1
+        } else {
+            let to_tail = ahead(tail_pos).saturating_sub(Hamiltonian::TAIL_MARGIN);
+            ahead(simulation.food().pos).min(to_tail).max(1)
+        };
+        // The next cell along the cycle is always a jump of one, so unless
+        // something else is in the way there's always a move to make. If there
+        // isn't, say in a versus game, we fall back on planning a way out.
+        view.moves()
+            .into_iter()
+            .filter(|&(_, pos)| (1..=max_jump).contains(&ahead(pos)))
+            .max_by_key(|&(_, pos)| ahead(pos))
+            .map_or_else(|| Pathfinder.next_dir(simulation, player), |(dir, _)| dir)
+    }
+}
+
+/// Lay out a cycle over a `width` by `height` board with an even `height`,
+/// turning each `(x, y)` it visits into a cell with `cell`.
+fn zig_zag(width: u16, height: u16, cell: impl Fn(u16, u16) -> GridPosition) -> Vec<GridPosition> {
+    let mut cycle = vec![cell(0, 0)];
+    for y in 0..height {
+        if y % 2 == 0 {
+            cycle.extend((1..width).map(|x| cell(x, y)));
+        } else {
+            cycle.extend((1..width).rev().map(|x| cell(x, y)));
+        }
+    }
+    cycle.extend((1..height).rev().map(|y| cell(0, y)));
+    cycle
+}
+
+/// What a bot needs to know about the game to plan the next move of one snake.
+struct View<'a> {
+    board: &'a Board,
+    /// Which way the snake last moved. It can't turn straight back on itself.
+    dir: Direction,
+    /// The snake's own cells, head first.
+    body: Vec<GridPosition>,
+    /// Which cells can't be moved into, indexed with `Board::index`. That
+    /// includes the snake's own tail, since the head lands before the tail moves
+    /// out of the way, but a path to the tail that's longer than a single move is
+    /// fine since by then it has moved on.
+    blocked: Vec<bool>,
+    /// Cells another snake's head could move into this tick. Going there risks
+    /// a head on crash, so we'd rather not.
+    contested: Vec<bool>,
+}
+
+impl<'a> View<'a> {
+    fn new(simulation: &'a Simulation, player: usize) -> Self {
+        let board = simulation.board();
+        let snake = &simulation.snakes()[player];
+        let body: Vec<GridPosition> = snake.positions().collect();
+
+        let mut blocked = vec![false; board.cells()];
+        let mut contested = vec![false; board.cells()];
+        for &pos in &board.walls {
+            blocked[board.index(pos)] = true;
+        }
+        for (other, snake) in simulation.snakes().iter().enumerate() {
+            for pos in snake.positions() {
+                blocked[board.index(pos)] = true;
+            }
+            if other != player && snake.is_alive() {
+                for dir in ALL_DIRECTIONS.iter() {
+                    if let Some(pos) = board.step(snake.head.pos, *dir) {
+                        contested[board.index(pos)] = true;
+                    }
+                }
+            }
+        }
+        View {
+            board,
+            dir: snake.last_update_dir,
+            body,
+            blocked,
+            contested,
+        }
+    }
+
+    /// Every move the snake can make without dying on the spot.
+    fn moves(&self) -> Vec<(Direction, GridPosition)> {
+        ALL_DIRECTIONS
+            .iter()
+            .filter(|&&dir| dir != self.dir.inverse())
+            .filter_map(|&dir| self.board.step(self.body[0], dir).map(|pos| (dir, pos)))
+            .filter(|&(_, pos)| !self.blocked[self.board.index(pos)])
+            .collect()
+    }
+
+    /// The moves that don't risk running head first into another snake, unless
+    /// that's all there is.
+    fn safe_moves(&self) -> Vec<(Direction, GridPosition)> {
+        let moves = self.moves();
+        let safe: Vec<_> = moves
+            .iter()
+            .copied()
+            .filter(|&(_, pos)| !self.contested[self.board.index(pos)])
+            .collect();
+        if safe.is_empty() {
+            moves
+        } else {
+            safe
+        }
+    }
+
+    /// Which way to go from the head to get to the neighbouring cell `pos`.
+    fn dir_to(&self, pos: GridPosition) -> Direction {
+        ALL_DIRECTIONS
+            .iter()
+            .copied()
+            .find(|&dir| self.board.step(self.body[0], dir) == Some(pos))
+            .unwrap_or(self.dir)
+    }
+
+    /// The shortest path from `from` to `to` through cells that aren't `blocked`,
+    /// found with a breadth first search. The path doesn't include `from`, and
+    /// is `None` if there's no way through.
+    fn path(
+        &self,
+        blocked: &[bool],
+        from: GridPosition,
+        to: GridPosition,
+    ) -> Option<Vec<GridPosition>> {
+        let mut came_from: Vec<Option<usize>> = vec![None; self.board.cells()];
+        let start = self.board.index(from);
+        let goal = self.board.index(to);
+        came_from[start] = Some(start);
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        while let Some(pos) = queue.pop_front() {
+            if self.board.index(pos) == goal {
+                let mut path = vec![pos];
+                let mut at = goal;
+                while let Some(prev) = came_from[at].filter(|&prev| prev != start) {
+                    path.push(self.board.position(prev));
+                    at = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for dir in ALL_DIRECTIONS.iter() {
+                if let Some(next) = self.board.step(pos, *dir) {
+                    let i = self.board.index(next);
+                    if came_from[i].is_none() && (!blocked[i] || i == goal) {
+                        came_from[i] = Some(self.board.index(pos));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether, having followed `path` to the food, the snake could still reach
+    /// its own tail. If it can it will never be boxed in by itself.
+    fn safe_after(&self, path: &[GridPosition]) -> bool {
+        // Eating the food makes the snake one longer, so it keeps one more of its
+        // old cells than it moved.
+        let mut body: Vec<GridPosition> = path.iter().rev().copied().collect();
+        body.extend(self.body.iter().copied());
+        body.truncate(self.body.len() + 1);
+
+        let mut blocked = self.blocked.clone();
+        for &pos in &self.body {
+            blocked[self.board.index(pos)] = false;
+        }
+        for &pos in &body {
+            blocked[self.board.index(pos)] = true;
+        }
+        let (head, tail) = (body[0], body[body.len() - 1]);
+        self.path(&blocked, head, tail).is_some()
+    }
+
+    /// How many free cells can be reached from `pos`.
+    fn room(&self, pos: GridPosition) -> usize {
+        let mut seen = self.blocked.clone();
+        let mut queue = VecDeque::new();
+        seen[self.board.index(pos)] = true;
+        queue.push_back(pos);
+        let mut count = 0;
+        while let Some(pos) = queue.pop_front() {
+            count += 1;
+            for dir in ALL_DIRECTIONS.iter() {
+                if let Some(next) = self.board.step(pos, *dir) {
+                    let i = self.board.index(next);
+                    if !seen[i] {
+                        seen[i] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        count
+    }
+}
+
+const ALL_DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::board::Boundary;
+    use crate::components::simulation::StepOutcome;
+
+    #[test]
+    fn the_autopilot_fills_small_boards() {
+        for &(width, height) in &[(4, 4), (6, 4), (4, 6), (8, 6), (5, 4)] {
+            for seed in 0..3 {
+                let board = Board::new(width, height, Boundary::Wrap);
+                assert!(
+                    Hamiltonian::check(board.clone(), seed).is_ok(),
+                    "{} by {} with seed {}",
+                    width,
+                    height,
+                    seed
+                );
+
+                let mut simulation = Simulation::new(board, seed);
+                let mut autopilot = Hamiltonian::default();
+                let mut outcome = StepOutcome::Moved;
+                while !simulation.is_over() {
+                    let dir = autopilot.next_dir(&simulation, 0);
+                    outcome = simulation.step(Some(dir));
+                }
+                assert_eq!(outcome, StepOutcome::Won);
+                assert!(simulation.has_won());
+            }
+        }
+    }
+
+    #[test]
+    fn without_a_cycle_the_autopilot_plays_like_a_pathfinder() {
+        let board = Board::new(5, 5, Boundary::Wrap);
+        assert!(Hamiltonian::cycle(&board).is_err());
+        assert!(Hamiltonian::check(board.clone(), 1).is_err());
+
+        let mut simulation = Simulation::new(board, 1);
+        let mut autopilot = Hamiltonian::default();
+        while !simulation.is_over() && simulation.ticks() < 1_000 {
+            let dir = autopilot.next_dir(&simulation, 0);
+            simulation.step(Some(dir));
+        }
+        assert!(simulation.food_eaten() > 0);
+    }
+}
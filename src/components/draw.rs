@@ -1,25 +1,304 @@
+use crate::components::board::Board;
+use crate::components::direction::Direction;
 use crate::components::grid_position::GridPosition;
-use ggez::graphics::{Align, Text};
-use ggez::{graphics, Context, GameResult};
+use crate::components::skin::{tint, Skin};
+use crate::SnakeConfig;
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{Align, DrawParam, Image, Mesh, MeshBuilder, Rect, Text};
+use ggez::mint::Point2;
+use ggez::{graphics, timer, Context, GameResult};
 use std::f32;
+use std::sync::Arc;
+use std::time::Duration;
 
-pub fn draw_rect(pos: GridPosition, color: [f32; 4], ctx: &mut Context) -> GameResult {
-    let rectangle =
-        graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), pos.into(), color.into())?;
-    graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
-    Ok(())
+/// The cells of the board gathered up to be drawn all at once. Making a mesh
+/// and a draw call for every single cell is fine for a small board, but a big
+/// one with long snakes has tens of thousands of cells, so instead every
+/// rectangle goes into one `MeshBuilder` and the board becomes a single mesh.
+/// Sprites from a skin likewise all go into one `SpriteBatch`.
+///
+/// Everything in a batch is drawn with the same `config`, whatever happens to
+/// the config while it is being filled.
+pub struct Batch {
+    builder: MeshBuilder,
+    cells: usize,
+    sprites: Option<SpriteBatch>,
+    config: Arc<SnakeConfig>,
+}
+
+impl Batch {
+    pub fn new(config: Arc<SnakeConfig>) -> Self {
+        Batch {
+            builder: MeshBuilder::new(),
+            cells: 0,
+            sprites: None,
+            config,
+        }
+    }
+
+    /// The config everything in the batch is drawn with.
+    pub fn config(&self) -> &SnakeConfig {
+        &self.config
+    }
+
+    /// Fill in the cell at `pos` with `color`.
+    pub fn draw_rect(&mut self, pos: GridPosition, color: [f32; 4]) {
+        self.draw_rect_at(cell_corner(pos, &self.config), color);
+    }
+
+    /// Fill in a cell sized rectangle at `at`, in pixels, which needn't line
+    /// up with the grid, with `color`.
+    pub fn draw_rect_at(&mut self, at: Point2<f32>, color: [f32; 4]) {
+        let rect = graphics::Rect::new(
+            at.x,
+            at.y,
+            self.config.cell_width as f32,
+            self.config.cell_height as f32,
+        );
+        self.builder
+            .rectangle(graphics::DrawMode::fill(), rect, color.into());
+        self.cells += 1;
+    }
+
+    /// Draw part of `atlas`, as `param` says. Every sprite in a batch has to
+    /// come from the same atlas.
+    pub fn draw_sprite(&mut self, atlas: &Image, param: DrawParam) {
+        self.sprites
+            .get_or_insert_with(|| SpriteBatch::new(atlas.clone()))
+            .add(param);
+    }
+
+    /// The mesh of every cell in the batch, or `None` if there aren't any,
+    /// since ggez can't make a mesh out of nothing. The sprites come along too.
+    fn build(self, ctx: &mut Context) -> GameResult<(Option<Mesh>, Option<SpriteBatch>)> {
+        if self.cells == 0 {
+            return Ok((None, self.sprites));
+        }
+        let mesh = self.builder.build(ctx)?;
+        Ok((Some(mesh), self.sprites))
+    }
+
+    /// Draw everything in the batch straight away, for things that change
+    /// every frame and so aren't worth keeping in a `CachedMesh`.
+    pub fn draw(self, ctx: &mut Context) -> GameResult {
+        let (mesh, sprites) = self.build(ctx)?;
+        if let Some(mesh) = &mesh {
+            graphics::draw(ctx, mesh, (Point2 { x: 0.0, y: 0.0 },))?;
+        }
+        if let Some(sprites) = &sprites {
+            graphics::draw(ctx, sprites, DrawParam::default())?;
+        }
+        Ok(())
+    }
 }
 
-pub fn draw_text(pos: GridPosition, text: String, ctx: &mut Context) -> GameResult {
-    let mut foo: Text = graphics::Text::default();
-    let _ = foo.set_bounds(
-        ggez::mint::Point2 {
-            x: f32::INFINITY,
+/// A mesh kept from one frame to the next. Most frames nothing on the board has
+/// moved since the last one, so the mesh is only built again when the
+/// `version` of what it shows changes.
+#[derive(Default)]
+pub struct CachedMesh {
+    mesh: Option<Mesh>,
+    sprites: Option<SpriteBatch>,
+    version: Option<u64>,
+}
+
+impl CachedMesh {
+    /// Draw the mesh for `version`, first filling a fresh batch drawn with
+    /// `config` with `fill` and building it if the one we have is out of date.
+    pub fn draw(
+        &mut self,
+        ctx: &mut Context,
+        config: &Arc<SnakeConfig>,
+        version: u64,
+        fill: impl FnOnce(&mut Batch),
+    ) -> GameResult {
+        if self.version != Some(version) {
+            let mut batch = Batch::new(config.clone());
+            fill(&mut batch);
+            let (mesh, sprites) = batch.build(ctx)?;
+            self.mesh = mesh;
+            self.sprites = sprites;
+            self.version = Some(version);
+        }
+        if let Some(mesh) = &self.mesh {
+            graphics::draw(ctx, mesh, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
+        }
+        if let Some(sprites) = &self.sprites {
+            graphics::draw(ctx, sprites, DrawParam::default())?;
+        }
+        Ok(())
+    }
+}
+
+/// How long frames are taking, shown over the game to keep an eye on how
+/// well it runs.
+#[derive(Default)]
+pub struct FrameTime {
+    /// How long drawing the board takes, smoothed over the last few frames.
+    board: f64,
+}
+
+impl FrameTime {
+    /// Take `time` spent drawing the board this frame into account.
+    pub fn board_drawn(&mut self, time: Duration) {
+        let ms = time.as_secs_f64() * 1000.0;
+        self.board = if self.board == 0.0 {
+            ms
+        } else {
+            self.board * 0.9 + ms * 0.1
+        };
+    }
+
+    /// Show the frames per second, how long a frame takes on average, and how
+    /// much of that is the board, in the bottom left corner.
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        let text = Text::new(format!(
+            "{:.0} fps, frame {:.2} ms, board {:.2} ms",
+            timer::fps(ctx),
+            timer::average_delta(ctx).as_secs_f64() * 1000.0,
+            self.board
+        ));
+        let height = graphics::screen_coordinates(ctx).h;
+        let y = height - text.height(ctx) as f32 - 4.0;
+        graphics::draw(ctx, &text, (ggez::mint::Point2 { x: 4.0, y },))
+    }
+}
+
+/// Show a message that needs the player's attention, such as what is wrong with
+/// the config, in red along the bottom of the window, clear of the frame time.
+pub fn draw_notice(message: &str, ctx: &mut Context) -> GameResult {
+    let screen = graphics::screen_coordinates(ctx);
+    let (width, height) = (screen.w, screen.h);
+    let mut text = Text::new(message);
+    text.set_bounds(
+        Point2 {
+            x: width - 8.0,
             y: f32::INFINITY,
         },
-        Align::Center,
+        Align::Left,
     );
-    let _ = foo.add(text);
-    graphics::draw(ctx, &foo, (pos,))?;
+    let line = Text::new("|").height(ctx) as f32;
+    let y = height - text.height(ctx) as f32 - line - 8.0;
+    let param = DrawParam::default()
+        .dest(Point2 { x: 4.0, y })
+        .color([1.0, 0.3, 0.3, 1.0].into());
+    graphics::draw(ctx, &text, param)
+}
+
+/// The top left corner of the cell at `pos`, in pixels, with the cells as big
+/// as `config` says.
+pub fn cell_corner(pos: GridPosition, config: &SnakeConfig) -> Point2<f32> {
+    Point2 {
+        x: pos.x as f32 * config.cell_width as f32,
+        y: pos.y as f32 * config.cell_height as f32,
+    }
+}
+
+/// Draw the snake of `player` heading `dir` covering `cells`, from its head to
+/// its tail.
+///
+/// Every player's snake has colors of its own, so we're told whose it is. With
+/// a `skin` it is drawn with sprites instead.
+pub fn draw_snake(
+    cells: &[GridPosition],
+    dir: Direction,
+    player: usize,
+    skin: Option<&Skin>,
+    batch: &mut Batch,
+) {
+    if let Some(skin) = skin {
+        skin.draw_snake(cells, dir, tint(player, batch.config()), batch);
+        return;
+    }
+    let (head, body) = batch.config().snake_colors(player);
+    // We first iterate through the body segments and draw them.
+    for &pos in cells.iter().skip(1) {
+        batch.draw_rect(pos, body);
+    }
+
+    // And then we do the same for the head, in its own color to distinguish it.
+    if let Some(&pos) = cells.first() {
+        batch.draw_rect(pos, head);
+    }
+}
+
+/// Draw the food at `pos`.
+pub fn draw_food(pos: GridPosition, skin: Option<&Skin>, batch: &mut Batch) {
+    match skin {
+        Some(skin) => skin.draw_food(pos, batch),
+        None => batch.draw_rect(pos, batch.config().food),
+    }
+}
+
+/// Draw the walls of `board`. The empty cells are just the background.
+pub fn draw_walls(board: &Board, batch: &mut Batch) {
+    let wall = batch.config().wall;
+    for &pos in &board.walls {
+        batch.draw_rect(pos, wall);
+    }
+}
+
+/// A board too big for the window is shown a window's worth at a time. This
+/// points the screen at the part of the board around `focus`, keeping it in the
+/// middle unless that would show past an edge, so whatever is drawn next lands
+/// in the right place on the board. When the whole board fits, that is all of it.
+pub fn follow(focus: GridPosition, config: &SnakeConfig, ctx: &mut Context) -> GameResult {
+    let (board_width, board_height) = config.board_size();
+    let (width, height) = config.screen_size();
+    let corner = cell_corner(focus, config);
+    let along = |at: f32, cell: u16, shown: f32, size: f32| {
+        (at + cell as f32 / 2.0 - shown / 2.0)
+            .max(0.0)
+            .min(size - shown)
+    };
+    let view = Rect::new(
+        along(corner.x, config.cell_width, width, board_width),
+        along(corner.y, config.cell_height, height, board_height),
+        width,
+        height,
+    );
+    graphics::set_screen_coordinates(ctx, view)
+}
+
+/// Point the screen back at the window after `follow`, for drawing the text
+/// that goes over the board.
+pub fn show_screen(config: &SnakeConfig, ctx: &mut Context) -> GameResult {
+    let (width, height) = config.screen_size();
+    graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width, height))
+}
+
+/// Outline the whole board in `color`.
+pub fn draw_border(color: [f32; 4], config: &SnakeConfig, ctx: &mut Context) -> GameResult {
+    // This is around the board rather than the window, so on a board bigger
+    // than the window it only shows along the edges we've scrolled to.
+    let (width, height) = config.board_size();
+    let thickness = 2.0;
+    let outline = graphics::Rect::new(
+        thickness / 2.0,
+        thickness / 2.0,
+        width - thickness,
+        height - thickness,
+    );
+    let rectangle = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::stroke(thickness),
+        outline,
+        color.into(),
+    )?;
+    graphics::draw(ctx, &rectangle, (ggez::mint::Point2 { x: 0.0, y: 0.0 },))?;
     Ok(())
 }
+
+/// Draw `text` over the board on `row`, counting rows of text down from the
+/// top of the window, and centered across it. It goes by the window rather than
+/// the cells, so it lands in the same place however big the cells or the board.
+pub fn draw_text(row: u16, text: String, ctx: &mut Context) -> GameResult {
+    let text = Text::new(text);
+    let window = graphics::screen_coordinates(ctx);
+    let line = Text::new("|").height(ctx) as f32 * 1.5;
+    let dest = Point2 {
+        x: window.x + ((window.w - text.width(ctx) as f32) / 2.0).max(0.0),
+        y: window.y + 4.0 + row as f32 * line,
+    };
+    graphics::draw(ctx, &text, (dest,))
+}
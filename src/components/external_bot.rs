@@ -0,0 +1,313 @@
+use crate::components::board::Boundary;
+use crate::components::controller::Controller;
+use crate::components::direction::Direction;
+use crate::components::grid_position::GridPosition;
+use crate::components::simulation::Simulation;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What an external bot is told about the game every tick, as a single line of JSON.
+#[derive(Serialize)]
+struct BotState {
+    tick: u64,
+    width: u16,
+    height: u16,
+    boundary: Boundary,
+    /// Which of `snakes` is the bot's own.
+    you: usize,
+    /// The score of the bot's own snake.
+    score: usize,
+    food: GridPosition,
+    walls: Vec<GridPosition>,
+    snakes: Vec<BotSnake>,
+}
+
+#[derive(Serialize)]
+struct BotSnake {
+    head: GridPosition,
+    /// The body from just behind the head to the tail.
+    body: Vec<GridPosition>,
+    dir: Direction,
+    alive: bool,
+    score: usize,
+}
+
+/// A reply can be just the direction, like `Up`, or a JSON object with it, like
+/// `{"dir": "Up"}`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Reply {
+    Dir(Direction),
+    Object { dir: Direction },
+}
+
+/// A bot that runs as a separate program, so it can be written in any language.
+/// Every tick we write the state of the game to its stdin as one line of JSON,
+/// and it writes back the direction it wants to go as one line on its stdout.
+///
+/// A bot that takes longer than its timeout to answer, answers with something
+/// we can't read, or has crashed, keeps going the way it was going. A late
+/// answer is thrown away rather than used for the next tick.
+///
+/// The question goes out as soon as the game asks whether the bot `is_ready`,
+/// and the answer comes in on the reader thread, so the window keeps drawing
+/// while the bot thinks.
+pub struct ExternalBot {
+    command: String,
+    child: Child,
+    /// The bot's stdin, until it stops accepting input.
+    stdin: Option<ChildStdin>,
+    /// Lines the bot writes, read on a thread of their own so that we can stop
+    /// waiting for them when the bot takes too long.
+    replies: Receiver<String>,
+    timeout: Duration,
+    /// Whether the bot has answered yet. Starting up can take a while, so until
+    /// it has we give it at least `STARTUP_TIMEOUT`.
+    started: bool,
+    /// The last question we asked the bot, if there has been one.
+    question: Option<Question>,
+}
+
+/// A tick we asked a bot about, and what came of it.
+struct Question {
+    tick: u64,
+    /// When we stop waiting for the answer.
+    deadline: Instant,
+    /// Whether we're done waiting, answered or not.
+    settled: bool,
+    answer: Option<Direction>,
+}
+
+impl ExternalBot {
+    const STARTUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Start `command` through the shell, so it can have arguments of its own.
+    pub fn spawn(command: &str, timeout: Duration) -> io::Result<Self> {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        let mut child = shell
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let stdout = child.stdout.take().expect("The bot's stdout is piped");
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(ExternalBot {
+            command: command.to_string(),
+            stdin: child.stdin.take(),
+            child,
+            replies,
+            timeout,
+            started: false,
+            question: None,
+        })
+    }
+
+    /// Tell the bot about the game, unless we already have for this tick.
+    fn ask(&mut self, simulation: &Simulation, player: usize) {
+        let tick = simulation.ticks();
+        if self.question.as_ref().is_some_and(|q| q.tick == tick) {
+            return;
+        }
+        // Anything the bot wrote since last time is an answer that came too late.
+        while self.replies.try_recv().is_ok() {}
+
+        let timeout = if self.started {
+            self.timeout
+        } else {
+            self.timeout.max(ExternalBot::STARTUP_TIMEOUT)
+        };
+        let mut question = Question {
+            tick,
+            deadline: Instant::now() + timeout,
+            settled: false,
+            answer: None,
+        };
+        match self.stdin.as_mut() {
+            Some(stdin) => {
+                let state = serde_json::to_string(&bot_state(simulation, player))
+                    .expect("The game state can always be written as JSON");
+                if let Err(e) = writeln!(stdin, "{}", state).and_then(|()| stdin.flush()) {
+                    eprintln!("Bot `{}` stopped listening: {}", self.command, e);
+                    self.stdin = None;
+                    question.settled = true;
+                }
+            }
+            None => question.settled = true,
+        }
+        self.question = Some(question);
+    }
+
+    /// Look for the answer to the last question, waiting for it until its
+    /// deadline if `wait`, and settle the question if we're done with it.
+    fn receive(&mut self, wait: bool) {
+        let question = match &mut self.question {
+            Some(question) if !question.settled => question,
+            _ => return,
+        };
+        let reply = if wait {
+            let left = question.deadline.saturating_duration_since(Instant::now());
+            self.replies.recv_timeout(left)
+        } else {
+            self.replies.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+            })
+        };
+        match reply {
+            Ok(line) => {
+                question.settled = true;
+                question.answer = parse_reply(&line);
+                match question.answer {
+                    Some(_) => self.started = true,
+                    None => eprintln!(
+                        "Bot `{}` gave a reply we can't read: {}",
+                        self.command, line
+                    ),
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if Instant::now() >= question.deadline => {
+                question.settled = true;
+                eprintln!(
+                    "Bot `{}` took too long to answer on tick {}",
+                    self.command, question.tick
+                );
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                question.settled = true;
+                eprintln!("Bot `{}` has stopped", self.command);
+                self.stdin = None;
+            }
+        }
+    }
+}
+
+impl Controller for ExternalBot {
+    fn next_dir(&mut self, simulation: &Simulation, player: usize) -> Direction {
+        self.ask(simulation, player);
+        self.receive(true);
+        let dir = simulation.snakes()[player].dir;
+        self.question
+            .as_ref()
+            .and_then(|question| question.answer)
+            .unwrap_or(dir)
+    }
+
+    fn is_ready(&mut self, simulation: &Simulation, player: usize) -> bool {
+        self.ask(simulation, player);
+        self.receive(false);
+        self.question
+            .as_ref()
+            .is_none_or(|question| question.settled)
+    }
+}
+
+impl Drop for ExternalBot {
+    /// A bot doesn't outlive its game.
+    fn drop(&mut self) {
+        self.stdin = None;
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn bot_state(simulation: &Simulation, player: usize) -> BotState {
+    let board = simulation.board();
+    BotState {
+        tick: simulation.ticks(),
+        width: board.width,
+        height: board.height,
+        boundary: board.boundary,
+        you: player,
+        score: simulation.score_of(player),
+        food: simulation.food().pos,
+        walls: board.walls.iter().copied().collect(),
+        snakes: simulation
+            .snakes()
+            .iter()
+            .map(|snake| BotSnake {
+                head: snake.head.pos,
+                body: snake.body.iter().map(|segment| segment.pos).collect(),
+                dir: snake.dir,
+                alive: snake.is_alive(),
+                score: snake.body.len(),
+            })
+            .collect(),
+    }
+}
+
+/// Read a direction from a line the bot wrote. Besides JSON we take the bare
+/// name of a direction in any case, like `up` or `LEFT`.
+fn parse_reply(line: &str) -> Option<Direction> {
+    let line = line.trim();
+    if let Ok(reply) = serde_json::from_str::<Reply>(line) {
+        return Some(match reply {
+            Reply::Dir(dir) | Reply::Object { dir } => dir,
+        });
+    }
+    match line.to_lowercase().as_str() {
+        "up" => Some(Direction::Up),
+        "down" => Some(Direction::Down),
+        "left" => Some(Direction::Left),
+        "right" => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+// The bots in these tests are shell scripts.
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::components::board::Board;
+
+    fn simulation() -> Simulation {
+        Simulation::new(Board::new(8, 8, Boundary::Wrap), 0)
+    }
+
+    #[test]
+    fn the_game_goes_on_while_the_bot_thinks() {
+        let simulation = simulation();
+        let command = "while read state; do sleep 0.2; echo up; done";
+        let mut bot = ExternalBot::spawn(command, Duration::from_secs(5)).unwrap();
+
+        let asked = Instant::now();
+        assert!(!bot.is_ready(&simulation, 0));
+        assert!(asked.elapsed() < Duration::from_millis(100));
+        while !bot.is_ready(&simulation, 0) {
+            assert!(asked.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(bot.next_dir(&simulation, 0), Direction::Up);
+    }
+
+    #[test]
+    fn a_bot_that_never_answers_keeps_going_the_same_way() {
+        let simulation = simulation();
+        let mut bot = ExternalBot::spawn("cat > /dev/null", Duration::from_millis(50)).unwrap();
+        bot.started = true;
+        assert!(!bot.is_ready(&simulation, 0));
+        assert_eq!(bot.next_dir(&simulation, 0), simulation.snake().dir);
+        assert!(bot.is_ready(&simulation, 0));
+    }
+}
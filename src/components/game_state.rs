@@ -1,57 +1,561 @@
+use crate::components::board::Boundary;
+use crate::components::campaign::{Campaign, Progress};
+use crate::components::controller::{Controller, ControllerKind, Hamiltonian};
 use crate::components::direction::Direction;
-use crate::components::draw::draw_text;
-use crate::components::food::{Ate, Food};
+use crate::components::draw::{
+    draw_border, draw_food, draw_notice, draw_snake, draw_text, draw_walls, follow, show_screen,
+    Batch, CachedMesh, FrameTime,
+};
 use crate::components::grid_position::GridPosition;
-use crate::components::snake::Snake;
-use crate::SNAKE_CONFIG;
-use ggez;
+use crate::components::high_scores::{HighScores, Score};
+use crate::components::level::Level;
+use crate::components::live_config::ConfigWatcher;
+use crate::components::motion::Motion;
+use crate::components::netcode::Netcode;
+use crate::components::replay::Replay;
+use crate::components::simulation::Simulation;
+use crate::components::skin::LoadedSkin;
+use crate::components::spectator::Spectators;
+use crate::{
+    config_path, reload_config, try_board, try_controller, try_controllers, SnakeConfig, CLI,
+    LEVEL, SNAKE_CONFIG,
+};
 use ggez::event::{KeyCode, KeyMods};
 use ggez::{event, graphics, Context, GameResult};
+use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
 /// Now we have the heart of our game, the GameState. This struct
 /// will implement ggez's `EventHandler` trait and will therefore drive
-/// everything else that happens in our game.
+/// everything else that happens in our game. The rules of the game live
+/// in the `Simulation`; the `GameState` just feeds it input, ticks it at
+/// the right rate and draws the result.
 pub struct GameState {
-    /// First we need a Snake
-    snake: Snake,
-    /// A piece of food
-    food: Food,
-    /// Whether the game is over or not
-    gameover: bool,
+    /// The game being played
+    simulation: Simulation,
+    /// The level it is being played on, if any
+    level: Option<Level>,
+    /// The campaign being played through, if any
+    campaign: Option<CampaignRun>,
+    /// The versus match being played, if there is more than one snake
+    versus: Option<Match>,
+    /// The bot steering each player's snake, or `None` where a person is
+    controllers: Vec<Option<Box<dyn Controller>>>,
+    /// Whether bots are playing on their own to show off the game until
+    /// someone presses Enter
+    demo: bool,
+    /// The game being played with others over the network, if it is
+    network: Option<Network>,
+    /// The server streaming the game to anyone watching, if there is one
+    spectators: Option<Spectators>,
+    /// Everything the player has done so far this game, so it can be saved as a replay
+    recording: Replay,
+    /// The directory finished games are saved to, if any
+    record_dir: Option<PathBuf>,
+    /// If we are watching a replay rather than playing, how far along it we are
+    playback: Option<Playback>,
+    /// The best games played so far
+    high_scores: HighScores,
+    /// What we are showing the player
+    screen: Screen,
+    /// The board as it was last drawn, kept until something on it changes
+    board_mesh: CachedMesh,
+    /// The sprites the snakes and food are drawn with, if there is a skin
+    skin: LoadedSkin,
+    /// Where the snakes are moving from, unless they're drawn a cell at a time
+    motion: Option<Motion>,
+    /// How long frames are taking, if that is being shown
+    frame_time: Option<FrameTime>,
+    /// Tells us when the config file is edited, so we can apply the changes,
+    /// unless the game is played by someone else's config
+    config_watcher: Option<ConfigWatcher>,
+    /// What is wrong with the config file, if the last edit didn't make sense
+    config_error: Option<String>,
     /// And we track the last time we updated so that we can limit
     /// our update rate.
     last_update: Instant,
 }
 
-impl GameState {
-    /// Our new function will set up the initial state of our game.
-    pub fn new() -> Self {
-        // First we put our snake a quarter of the way across our grid in the x axis
-        // and half way down the y axis. This works well since we start out moving to the right.
-        let snake_pos = (SNAKE_CONFIG.grid_width / 4, SNAKE_CONFIG.grid_height / 2).into();
-        // Then we choose a random place to put our piece of food using the helper we made
-        // earlier.
-        let food_pos = GridPosition::random(SNAKE_CONFIG.grid_width, SNAKE_CONFIG.grid_height);
+/// The different things we can show the player once a game is over.
+enum Screen {
+    /// The board, along with the game over message if the game has ended
+    Game,
+    /// Asking the player their name for the new high score they just set
+    EnterName(Score),
+    /// The high score table for the board size and speed of this score
+    HighScores(Score),
+    /// Choosing which unlocked stage of the campaign to play
+    StageSelect,
+    /// Telling the player they finished a stage of the campaign
+    StageComplete,
+    /// Telling the players who won the round of a versus match that just ended,
+    /// if anyone did
+    RoundOver(Option<usize>),
+    /// Telling the players who won the versus match
+    MatchOver(usize),
+}
+
+/// How a versus match is going.
+struct Match {
+    /// How many rounds each player has won
+    wins: Vec<usize>,
+    /// The round being played, counting from 1
+    round: usize,
+}
+
+impl Match {
+    fn new(players: usize) -> Self {
+        Match {
+            wins: vec![0; players],
+            round: 1,
+        }
+    }
+
+    /// The player who has won enough rounds to take the match, if anyone has.
+    fn champion(&self) -> Option<usize> {
+        self.wins
+            .iter()
+            .position(|&wins| wins >= SNAKE_CONFIG.get().rounds_to_win)
+    }
+}
+
+/// A game played with others over the network.
+struct Network {
+    session: Box<dyn Netcode>,
+    /// The bot playing the local player's snake, if a person isn't.
+    bot: Option<Box<dyn Controller>>,
+    /// Why the game couldn't go on, if it couldn't.
+    error: Option<String>,
+}
+
+/// How far through a campaign the player is.
+struct CampaignRun {
+    campaign: Campaign,
+    /// The index of the stage being played, or about to be played
+    stage: usize,
+    /// The score from every stage finished so far this run
+    total_score: usize,
+    progress: Progress,
+}
 
+impl CampaignRun {
+    fn last_stage(&self) -> bool {
+        self.stage + 1 == self.campaign.stages.len()
+    }
+}
+
+/// The state of a replay being watched.
+struct Playback {
+    replay: Replay,
+    /// The index of the next input in `replay` to be applied
+    cursor: usize,
+    paused: bool,
+    /// How many times faster than normal the replay runs
+    speed: f32,
+}
+
+impl Playback {
+    const MIN_SPEED: f32 = 0.25;
+    const MAX_SPEED: f32 = 16.0;
+    /// The most ticks we step in one frame, so a replay sped up beyond what we
+    /// can keep up with still gets drawn.
+    const MAX_TICKS_PER_FRAME: u32 = 64;
+
+    /// Step the replayed game once, unless it has already reached the end of the recording.
+    fn step(&mut self, simulation: &mut Simulation) {
+        if !self.finished(simulation) {
+            self.replay.step(simulation, &mut self.cursor);
+        }
+    }
+
+    fn finished(&self, simulation: &Simulation) -> bool {
+        simulation.ticks() >= self.replay.ticks || simulation.is_over()
+    }
+}
+
+impl GameState {
+    /// Our new function will set up the initial state of our game. The `seed`
+    /// decides where the food goes, so the same seed always plays out the same game.
+    /// If `record_dir` is given, every game played is saved there as a replay. With
+    /// more than one of `players` they play a versus match, the second player
+    /// steering with WASD and any more played by bots.
+    pub fn new(seed: u64, record_dir: Option<PathBuf>, players: usize) -> Self {
+        let board = try_board(LEVEL.as_ref(), players);
         GameState {
-            snake: Snake::new(snake_pos),
-            food: Food::new(food_pos),
-            gameover: false,
+            simulation: Simulation::with_players(board, players, seed),
+            level: LEVEL.clone(),
+            campaign: None,
+            versus: if players > 1 {
+                Some(Match::new(players))
+            } else {
+                None
+            },
+            controllers: try_controllers(players),
+            demo: false,
+            network: None,
+            spectators: None,
+            recording: Replay::new(
+                seed,
+                SNAKE_CONFIG.get().as_ref().clone(),
+                LEVEL.clone(),
+                players,
+            ),
+            record_dir,
+            playback: None,
+            high_scores: HighScores::load(),
+            screen: Screen::Game,
+            board_mesh: CachedMesh::default(),
+            skin: LoadedSkin::default(),
+            motion: motion(),
+            frame_time: frame_time(),
+            config_watcher: Some(ConfigWatcher::new(config_path())),
+            config_error: None,
             last_update: Instant::now(),
         }
     }
 
-    fn restart(&mut self) {
-        let snake_pos = (SNAKE_CONFIG.grid_width / 4, SNAKE_CONFIG.grid_height / 2).into();
-        self.snake = Snake::new(snake_pos);
-        self.food = Food::new(GridPosition::random(
-            SNAKE_CONFIG.grid_width,
-            SNAKE_CONFIG.grid_height,
-        ));
-        self.gameover = false;
+    /// Set up an attract mode where bots play every snake, starting a new game
+    /// whenever they die, until someone presses Enter to play for real. A lone
+    /// snake is played by the autopilot if it can be, so it fills the board.
+    pub fn demo(seed: u64, record_dir: Option<PathBuf>, players: usize) -> Self {
+        let mut state = GameState::new(seed, record_dir, players);
+        let bot = if players == 1 && Hamiltonian::cycle(state.simulation.board()).is_ok() {
+            ControllerKind::Hamiltonian
+        } else {
+            ControllerKind::Pathfinder
+        };
+        state.controllers = (0..state.simulation.players())
+            .map(|_| try_controller(bot))
+            .collect();
+        state.demo = true;
+        state
+    }
+
+    /// Leave the demo and start a real game with the controllers from the config.
+    fn end_demo(&mut self) {
+        self.demo = false;
+        self.controllers = try_controllers(self.simulation.players());
+        self.restart(rand::random());
+    }
+
+    /// Set up a game that plays through the stages of `campaign`, starting with the
+    /// player choosing which of the stages they have unlocked to play.
+    pub fn campaign(campaign: Campaign, seed: u64, record_dir: Option<PathBuf>) -> Self {
+        let progress = Progress::load();
+        let stage = progress.unlocked(&campaign) - 1;
+        let mut state = GameState::new(seed, record_dir, 1);
+        state.campaign = Some(CampaignRun {
+            campaign,
+            stage,
+            total_score: 0,
+            progress,
+        });
+        state.screen = Screen::StageSelect;
+        state
+    }
+
+    /// Set up a game played with others over the network through `session`.
+    /// The local player steers with the arrow keys, unless the first of the
+    /// config's controllers is a bot, which then plays for them.
+    pub fn network(session: Box<dyn Netcode>, record_dir: Option<PathBuf>) -> Self {
+        let setup = session.setup().clone();
+        let mut state = GameState::new(setup.seed, record_dir, 1);
+        state.simulation = Simulation::with_players(
+            try_board(LEVEL.as_ref(), setup.players),
+            setup.players,
+            setup.seed,
+        );
+        state.recording = Replay::new(
+            setup.seed,
+            SNAKE_CONFIG.get().as_ref().clone(),
+            LEVEL.clone(),
+            setup.players,
+        );
+        state.controllers = Vec::new();
+        // Everyone has to play by the host's rules.
+        state.config_watcher = None;
+        state.network = Some(Network {
+            session,
+            bot: try_controller(SNAKE_CONFIG.get().controller(0)),
+            error: None,
+        });
+        state
+    }
+
+    /// Stream every game played to the viewers of `spectators`, if there are any.
+    pub fn with_spectators(mut self, spectators: Option<Spectators>) -> Self {
+        self.spectators = spectators;
+        self
+    }
+
+    /// Set up a game that plays back `replay` instead of listening to the player,
+    /// or say why it can't be played back.
+    pub fn replay(replay: Replay) -> Result<Self, String> {
+        Ok(GameState {
+            simulation: replay.start()?,
+            level: replay.level.clone(),
+            campaign: None,
+            versus: None,
+            controllers: Vec::new(),
+            demo: false,
+            network: None,
+            spectators: None,
+            recording: Replay::new(
+                replay.seed,
+                replay.config.clone(),
+                replay.level.clone(),
+                replay.players,
+            ),
+            record_dir: None,
+            playback: Some(Playback {
+                replay,
+                cursor: 0,
+                paused: false,
+                speed: 1.0,
+            }),
+            high_scores: HighScores::default(),
+            screen: Screen::Game,
+            board_mesh: CachedMesh::default(),
+            skin: LoadedSkin::default(),
+            motion: motion(),
+            frame_time: frame_time(),
+            config_watcher: None,
+            config_error: None,
+            last_update: Instant::now(),
+        })
+    }
+
+    fn restart(&mut self, seed: u64) {
+        let players = self.simulation.players();
+        let board = try_board(self.level.as_ref(), players);
+        self.simulation = Simulation::with_players(board, players, seed);
+        self.recording = Replay::new(
+            seed,
+            SNAKE_CONFIG.get().as_ref().clone(),
+            self.level.clone(),
+            players,
+        );
+        self.screen = Screen::Game;
         self.last_update = Instant::now();
     }
+
+    /// Apply the config file again after it was edited. Colors and speed change
+    /// straight away, while a board of a new size needs a window to match and
+    /// a new game. If the edit doesn't make sense we say why and carry on with
+    /// the config we have.
+    fn apply_config_changes(&mut self, ctx: &mut Context) -> GameResult {
+        let level = self.level.as_ref();
+        let checked = reload_config().and_then(|config| match level {
+            Some(level) => level
+                .board(&config)
+                .map(|_| config)
+                .map_err(|e| format!("Error with level {}: {}", level.name, e)),
+            None => Ok(config),
+        });
+        let config = match checked {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("{}", e);
+                self.config_error = Some(e);
+                return Ok(());
+            }
+        };
+        let new_board = SNAKE_CONFIG.get().changes_board(&config);
+        SNAKE_CONFIG.replace(config);
+        self.config_error = None;
+        // Whatever we've drawn or loaded may look different now.
+        self.board_mesh = CachedMesh::default();
+        self.skin = LoadedSkin::default();
+        self.motion = motion();
+        if new_board {
+            let config = SNAKE_CONFIG.get();
+            let (width, height) = config.screen_size();
+            // Fullscreen, the board is stretched to fit the screen instead.
+            if !config.fullscreen {
+                graphics::set_drawable_size(ctx, width, height)?;
+            }
+            graphics::set_screen_coordinates(ctx, graphics::Rect::new(0.0, 0.0, width, height))?;
+            self.restart(rand::random());
+        }
+        Ok(())
+    }
+
+    /// Called once when a game ends. If the player set a new high score we ask them
+    /// their name. In a versus match we score the round instead.
+    fn game_over(&mut self) {
+        self.save_recording();
+        if let Some(versus) = &mut self.versus {
+            let winner = self.simulation.winner();
+            if let Some(winner) = winner {
+                versus.wins[winner] += 1;
+            }
+            self.screen = match versus.champion() {
+                Some(champion) => Screen::MatchOver(champion),
+                None => Screen::RoundOver(winner),
+            };
+            return;
+        }
+        let score = Score::new(&self.simulation, &SNAKE_CONFIG.get(), self.level.as_ref());
+        if self.high_scores.qualifies(&score) {
+            self.screen = Screen::EnterName(score);
+        }
+    }
+
+    /// Start playing the stage of the campaign that has been chosen.
+    fn start_stage(&mut self) {
+        if let Some(run) = &self.campaign {
+            self.level = run.campaign.stages[run.stage].level.clone();
+            self.restart(rand::random());
+        }
+    }
+
+    /// Called once the goal of the current campaign stage is reached. We add the
+    /// score to the running total and unlock the next stage.
+    fn stage_complete(&mut self) {
+        self.save_recording();
+        if let Some(run) = &mut self.campaign {
+            run.total_score += self.simulation.score();
+            run.progress.unlock_after(&run.campaign, run.stage);
+            if let Err(e) = run.progress.save() {
+                eprintln!("Couldn't save campaign progress: {}", e);
+            }
+        }
+        self.screen = Screen::StageComplete;
+    }
+
+    /// Put the score the player just named into the table and show them where it landed.
+    fn enter_high_score(&mut self, score: Score) {
+        self.high_scores.insert(score.clone());
+        if let Err(e) = self.high_scores.save() {
+            eprintln!("Couldn't save high scores: {}", e);
+        }
+        self.screen = Screen::HighScores(score);
+    }
+
+    /// Write the game played so far to `record_dir`, named after its seed.
+    fn save_recording(&mut self) {
+        if let Some(dir) = &self.record_dir {
+            self.recording.finish(&self.simulation);
+            let path = dir.join(format!("snake-{}.json", self.recording.seed));
+            if let Err(e) = self.recording.save(&path) {
+                eprintln!("Couldn't save replay to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// Whether every bot still playing knows which way it's going next.
+    fn bots_ready(&mut self) -> bool {
+        let simulation = &self.simulation;
+        self.controllers
+            .iter_mut()
+            .enumerate()
+            .all(|(player, controller)| match controller {
+                Some(controller) if simulation.snakes()[player].is_alive() => {
+                    controller.is_ready(simulation, player)
+                }
+                _ => true,
+            })
+    }
+
+    /// Let every bot decide which way its snake goes next. We record their moves
+    /// just like key presses, so games against bots replay the same as any other.
+    fn steer_bots(&mut self) {
+        for (player, controller) in self.controllers.iter_mut().enumerate() {
+            if let Some(controller) = controller {
+                if self.simulation.snakes()[player].is_alive() {
+                    let dir = controller.next_dir(&self.simulation, player);
+                    self.recording.record(&self.simulation, player, dir);
+                    self.simulation.steer_player(player, dir);
+                }
+            }
+        }
+    }
+
+    /// Try to play the next tick of a networked game, which with lockstep can
+    /// only happen once everyone's input for it has arrived. Gives whether it
+    /// was played. Rollback can still change how a game that is over ended, so
+    /// we keep at it until the session says the game is settled.
+    fn step_network(&mut self) -> bool {
+        let network = match &mut self.network {
+            Some(network)
+                if network.error.is_none() && !network.session.is_settled(&self.simulation) =>
+            {
+                network
+            }
+            _ => return false,
+        };
+        let player = network.session.player();
+        if network.session.needs_input(&self.simulation)
+            && self.simulation.snakes()[player].is_alive()
+        {
+            if let Some(bot) = &mut network.bot {
+                if !bot.is_ready(&self.simulation, player) {
+                    return false;
+                }
+                let dir = bot.next_dir(&self.simulation, player);
+                network.session.steer(dir);
+            }
+        }
+        match network
+            .session
+            .tick(&mut self.simulation, &mut self.recording)
+        {
+            Ok(stepped) => {
+                if network.session.is_settled(&self.simulation) {
+                    if let Some(summary) = network.session.summary() {
+                        println!("{}", summary);
+                    }
+                    self.save_recording();
+                }
+                stepped
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                network.error = Some(e);
+                self.save_recording();
+                false
+            }
+        }
+    }
+
+    /// Whether we are playing a campaign stage and the snake just reached its
+    /// goal. Filling the board on the same tick still counts, but dying doesn't.
+    fn stage_goal_reached(&self) -> bool {
+        match &self.campaign {
+            Some(run) => {
+                (!self.simulation.is_over() || self.simulation.has_won())
+                    && run.campaign.stages[run.stage]
+                        .goal
+                        .reached(&self.simulation)
+            }
+            None => false,
+        }
+    }
+
+    /// How long to wait between ticks, at the update rate in `config`, sped up
+    /// or slowed down if watching a replay.
+    fn interval(&self, config: &SnakeConfig) -> Duration {
+        let speed = self
+            .playback
+            .as_ref()
+            .map_or(1.0, |playback| playback.speed);
+        Duration::from_secs_f32(1.0 / (config.updates_per_second * speed))
+    }
+
+    /// While watching a replay the keys control the playback instead of the snake.
+    fn playback_key(&mut self, ctx: &mut Context, keycode: KeyCode) {
+        if let Some(playback) = &mut self.playback {
+            match keycode {
+                KeyCode::Space => playback.paused = !playback.paused,
+                // Step a single tick at a time while paused.
+                KeyCode::Right if playback.paused => playback.step(&mut self.simulation),
+                KeyCode::Up => playback.speed = (playback.speed * 2.0).min(Playback::MAX_SPEED),
+                KeyCode::Down => playback.speed = (playback.speed / 2.0).max(Playback::MIN_SPEED),
+                KeyCode::Escape => event::quit(ctx),
+                _ => {}
+            }
+        }
+    }
 }
 
 /// Now we implement EventHandler for GameState. This provides an interface
@@ -59,38 +563,74 @@ impl GameState {
 impl event::EventHandler for GameState {
     /// Update will happen on every frame before it is drawn. This is where we update
     /// our game state to react to whatever is happening in the game world.
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        // First we check to see if enough time has elapsed since our last update based on
-        // the update rate we defined at the top.
-        if Instant::now() - self.last_update
-            >= Duration::from_millis((1.0 / SNAKE_CONFIG.updates_per_second * 1000.0) as u64)
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        // If the config file was edited we play by it from now on.
+        if self
+            .config_watcher
+            .as_mut()
+            .is_some_and(ConfigWatcher::changed)
         {
-            // Then we check to see if the game is over. If not, we'll update. If so, we'll just do nothing.
-            if !self.gameover {
-                // Here we do the actual updating of our game world. First we tell the snake to update itself,
-                // passing in a reference to our piece of food.
-                self.snake.update(&self.food);
-                // Next we check if the snake ate anything as it updated.
-                if let Some(ate) = self.snake.ate {
-                    // If it did, we want to know what it ate.
-                    match ate {
-                        // If it ate a piece of food, we randomly select a new position for our piece of food
-                        // and move it to this new position.
-                        Ate::Food => {
-                            let new_food_pos = GridPosition::random(
-                                SNAKE_CONFIG.grid_width,
-                                SNAKE_CONFIG.grid_height,
-                            );
-                            self.food.pos = new_food_pos;
-                        }
-                        // If it ate itself, we set our gameover state to true.
-                        Ate::Itself => {
-                            self.gameover = true;
-                            //draw_text(GridPosition::from((15u8, 15u8)), t, _ctx)?;
-                            //let _ = event::quit(_ctx);
-                            return Ok(());
-                        }
+            self.apply_config_changes(ctx)?;
+        }
+        // Anyone watching sees whatever happened since the last frame.
+        if let Some(spectators) = &mut self.spectators {
+            spectators.update(&self.simulation);
+        }
+        // First we check to see if enough time has elapsed since our last update based on
+        // the update rate we defined at the top, sped up or slowed down if watching a replay.
+        let interval = self.interval(&SNAKE_CONFIG.get());
+        // A networked game may have to wait for someone's input, so we only count
+        // it as updated once it has actually moved on.
+        if let Some(network) = &mut self.network {
+            network.session.flush();
+            if Instant::now() - self.last_update >= interval && self.step_network() {
+                self.last_update = Instant::now();
+            }
+            return Ok(());
+        }
+        // When watching a replay, the replay tells the snake where to go. Sped up,
+        // ticks can come due more often than frames are drawn, so we step every
+        // tick that has come due since the last frame.
+        if let Some(playback) = &mut self.playback {
+            let mut stepped = 0;
+            while !playback.paused
+                && self.last_update.elapsed() >= interval
+                && stepped < Playback::MAX_TICKS_PER_FRAME
+            {
+                playback.step(&mut self.simulation);
+                self.last_update += interval;
+                stepped += 1;
+            }
+            // If we can't keep up we carry on from here rather than rushing to catch up.
+            if playback.paused || stepped == Playback::MAX_TICKS_PER_FRAME {
+                self.last_update = Instant::now();
+            }
+            return Ok(());
+        }
+        if Instant::now() - self.last_update >= interval {
+            // Otherwise we step the simulation, and save the game if that ended it.
+            // Once the game is over this does nothing, and neither does it while
+            // we're showing some other screen.
+            if let Screen::Game = self.screen {
+                let was_over = self.simulation.is_over();
+                if !was_over {
+                    // A bot that is still thinking holds up the tick, but
+                    // not the frames, so we come back to it on the next one.
+                    if !self.bots_ready() {
+                        return Ok(());
                     }
+                    self.steer_bots();
+                }
+                self.simulation.step(None);
+                if self.demo {
+                    // The demo goes on until someone wants to play.
+                    if self.simulation.is_over() {
+                        self.restart(rand::random());
+                    }
+                } else if self.stage_goal_reached() {
+                    self.stage_complete();
+                } else if !was_over && self.simulation.is_over() {
+                    self.game_over();
                 }
             }
             // If we updated, we set our last_update to be now
@@ -103,26 +643,120 @@ impl event::EventHandler for GameState {
     /// draw is where we should actually render the game's current state.
     fn draw(&mut self, ctx: &mut Context) -> GameResult {
         // First we clear the screen to a nice (well, maybe pretty glaring ;)) green
-        graphics::clear(ctx, SNAKE_CONFIG.background.into());
-        // Then we tell the snake and the food to draw themselves
-        self.snake.draw(ctx)?;
-        self.food.draw(ctx)?;
-
-        if self.gameover {
-            let game_over_text = format!("Snake Length Score {}.", self.snake.body.len());
-            draw_text(GridPosition::from((11u8, 1u8)), game_over_text, ctx)?;
-
-            draw_text(
-                GridPosition::from((10u8, 2u8)),
-                String::from("Press Enter to Play Again."),
-                ctx,
-            )?;
-
-            draw_text(
-                GridPosition::from((11u8, 3u8)),
-                String::from("Press Escape to Exit."),
-                ctx,
-            )?;
+        // Everything in the frame is drawn with the config as it is now.
+        let config = SNAKE_CONFIG.get();
+        graphics::clear(ctx, config.background.into());
+        // A board bigger than the window scrolls to keep the first snake in view.
+        follow(self.simulation.snake().head.pos, &config, ctx)?;
+        // If the edges of the board are walls, we outline them so the player knows.
+        if config.boundary == Boundary::Walls {
+            draw_border(config.wall, &config, ctx)?;
+        }
+        // Then everything on the board draws itself into a single mesh, which we
+        // only build again when something has moved.
+        let started = Instant::now();
+        let interval = self.interval(&config);
+        let simulation = &self.simulation;
+        let skin = self.skin.get(ctx);
+        // When the snakes move smoothly only their heads and tails move between
+        // ticks, so the rest of them can still go in the mesh.
+        let motion = match &mut self.motion {
+            Some(motion) => {
+                motion.observe(simulation);
+                Some(&*motion)
+            }
+            None => None,
+        };
+        self.board_mesh
+            .draw(ctx, &config, board_version(simulation), |batch| {
+                // First the walls of the level, if there are any
+                draw_walls(simulation.board(), batch);
+                // And we tell the snakes and the food to draw themselves. Once the board is
+                // full the last piece of food has been eaten, so there is nothing to draw.
+                for (player, snake) in simulation.snakes().iter().enumerate() {
+                    match motion {
+                        Some(motion) => motion.draw_still(snake, player, skin, batch),
+                        None => {
+                            let cells: Vec<GridPosition> = snake.positions().collect();
+                            draw_snake(&cells, snake.dir, player, skin, batch)
+                        }
+                    }
+                }
+                if !simulation.has_won() {
+                    draw_food(simulation.food().pos, skin, batch);
+                }
+            })?;
+        if let Some(motion) = motion {
+            let progress = motion.progress(interval);
+            let mut moving = Batch::new(config.clone());
+            for (player, snake) in simulation.snakes().iter().enumerate() {
+                motion.draw_moving(snake, player, skin, progress, &mut moving);
+            }
+            moving.draw(ctx)?;
+        }
+        if let Some(frame_time) = &mut self.frame_time {
+            frame_time.board_drawn(started.elapsed());
+        }
+        show_screen(&config, ctx)?;
+
+        if let Some(playback) = &self.playback {
+            let status = format!(
+                "Replay {}x, tick {} of {}{}",
+                playback.speed,
+                self.simulation.ticks(),
+                playback.replay.ticks,
+                if playback.paused { ", paused" } else { "" }
+            );
+            draw_text(0, status, ctx)?;
+
+            if playback.paused {
+                draw_text(1, String::from("Space to Resume, Right to Step."), ctx)?;
+            }
+
+            if playback.finished(&self.simulation) {
+                let end_text = format!("End of Replay, Score {}.", self.simulation.score());
+                draw_text(2, end_text, ctx)?;
+
+                draw_text(3, String::from("Press Escape to Exit."), ctx)?;
+            }
+        } else if self.demo {
+            draw_text(1, String::from("Snake!"), ctx)?;
+            draw_text(2, String::from("Press Enter to Play."), ctx)?;
+        } else if let Some(network) = &self.network {
+            draw_network(network, &self.simulation, ctx)?;
+        } else if let Screen::EnterName(score) = &self.screen {
+            draw_enter_name(score, ctx)?;
+        } else if let Screen::HighScores(score) = &self.screen {
+            draw_high_scores(&self.high_scores, score, ctx)?;
+        } else if let (Screen::StageSelect, Some(run)) = (&self.screen, &self.campaign) {
+            draw_stage_select(run, ctx)?;
+        } else if let (Screen::StageComplete, Some(run)) = (&self.screen, &self.campaign) {
+            draw_stage_complete(run, ctx)?;
+        } else if let Some(versus) = &self.versus {
+            draw_versus(versus, &self.simulation, &self.screen, ctx)?;
+        } else if self.simulation.is_over() {
+            let game_over_text = if self.simulation.has_won() {
+                format!("Board Full, You Win! Score {}.", self.simulation.score())
+            } else {
+                format!("Snake Length Score {}.", self.simulation.score())
+            };
+            draw_text(1, game_over_text, ctx)?;
+
+            draw_text(2, String::from("Press Enter to Play Again."), ctx)?;
+
+            draw_text(3, String::from("Press Escape to Exit."), ctx)?;
+
+            draw_text(4, String::from("Press H for High Scores."), ctx)?;
+
+            // Show the seed so that anyone reporting a bug can tell us how to replay it.
+            let seed_text = format!("Seed {}.", self.simulation.seed());
+            draw_text(5, seed_text, ctx)?;
+        }
+        if let Some(error) = &self.config_error {
+            draw_notice(error, ctx)?;
+        }
+        if let Some(frame_time) = &self.frame_time {
+            frame_time.draw(ctx)?;
         }
         // Finally we call graphics::present to cycle the gpu's framebuffer and display
         // the new frame we just drew.
@@ -141,27 +775,398 @@ impl event::EventHandler for GameState {
         _keymod: KeyMods,
         _repeat: bool,
     ) {
+        // F3 shows or hides how long frames are taking, whatever else is going on.
+        if keycode == KeyCode::F3 {
+            self.frame_time = match self.frame_time {
+                Some(_) => None,
+                None => Some(FrameTime::default()),
+            };
+            return;
+        }
+
+        if self.playback.is_some() {
+            self.playback_key(_ctx, keycode);
+            return;
+        }
+
+        // In a networked game the arrow keys steer our own snake, by way of
+        // everyone else, unless a bot is playing it.
+        if let Some(network) = &mut self.network {
+            match arrow_direction(keycode) {
+                Some(dir) if network.bot.is_none() => network.session.steer(dir),
+                _ if keycode == KeyCode::Escape => event::quit(_ctx),
+                _ => {}
+            }
+            return;
+        }
+
+        // While the demo runs, the bots are in charge of the snakes.
+        if self.demo {
+            match keycode {
+                KeyCode::Return => self.end_demo(),
+                KeyCode::Escape => event::quit(_ctx),
+                _ => {}
+            }
+            return;
+        }
+
+        // The high score screens have keys of their own.
+        match &mut self.screen {
+            Screen::EnterName(score) => {
+                match keycode {
+                    KeyCode::Back => {
+                        score.name.pop();
+                    }
+                    KeyCode::Return => {
+                        let score = score.clone();
+                        self.enter_high_score(score);
+                    }
+                    _ => {}
+                }
+                return;
+            }
+            Screen::HighScores(_) => {
+                match keycode {
+                    KeyCode::Return => self.restart(rand::random()),
+                    KeyCode::Escape => event::quit(_ctx),
+                    KeyCode::H | KeyCode::Back => self.screen = Screen::Game,
+                    _ => {}
+                }
+                return;
+            }
+            Screen::StageSelect => {
+                if let Some(run) = &mut self.campaign {
+                    let unlocked = run.progress.unlocked(&run.campaign);
+                    match keycode {
+                        KeyCode::Left => run.stage = run.stage.saturating_sub(1),
+                        KeyCode::Right => run.stage = (run.stage + 1).min(unlocked - 1),
+                        KeyCode::Return => {
+                            run.total_score = 0;
+                            self.start_stage();
+                        }
+                        KeyCode::Escape => event::quit(_ctx),
+                        _ => {}
+                    }
+                }
+                return;
+            }
+            Screen::RoundOver(_) | Screen::MatchOver(_) => {
+                match keycode {
+                    KeyCode::Return => {
+                        if let Some(versus) = &mut self.versus {
+                            // Once the match is won a fresh one starts.
+                            if let Screen::MatchOver(_) = self.screen {
+                                *versus = Match::new(versus.wins.len());
+                            } else {
+                                versus.round += 1;
+                            }
+                        }
+                        self.restart(rand::random());
+                    }
+                    KeyCode::Escape => event::quit(_ctx),
+                    _ => {}
+                }
+                return;
+            }
+            Screen::StageComplete => {
+                if let Some(run) = &mut self.campaign {
+                    if keycode == KeyCode::Return {
+                        // After the last stage we go back to choosing one.
+                        if run.last_stage() {
+                            self.screen = Screen::StageSelect;
+                        } else {
+                            run.stage += 1;
+                            self.start_stage();
+                        }
+                    } else if keycode == KeyCode::Escape {
+                        event::quit(_ctx);
+                    }
+                }
+                return;
+            }
+            Screen::Game => {}
+        }
+
         // Here we attempt to convert the Keycode into a Direction using the helper
-        // we defined earlier.
-        if let Some(dir) = Direction::from_keycode(keycode) {
-            // If it succeeds, we check if a new direction has already been set
-            // and make sure the new direction is different then `snake.dir`
-            if self.snake.dir != self.snake.last_update_dir && dir.inverse() != self.snake.dir {
-                self.snake.buffered_dir = Some(dir);
-            } else if dir.inverse() != self.snake.last_update_dir {
-                // If no new direction has been set and the direction is not the inverse
-                // of the `last_update_dir`, then set the snake's new direction to be the
-                // direction the user pressed.
-                self.snake.dir = dir;
+        // we defined below, and if it succeeds we steer the snake that way and
+        // remember that we did for the replay. In a versus match the second
+        // player's snake is steered with WASD.
+        let steering = match arrow_direction(keycode) {
+            Some(dir) => Some((0, dir)),
+            None if self.versus.is_some() => wasd_direction(keycode).map(|dir| (1, dir)),
+            None => None,
+        };
+        if let Some((player, dir)) = steering {
+            // Bots don't take orders from the keyboard.
+            let is_bot = self.controllers.get(player).is_some_and(Option::is_some);
+            if !is_bot && !self.simulation.is_over() {
+                self.recording.record(&self.simulation, player, dir);
+                self.simulation.steer_player(player, dir);
             }
         }
 
-        if self.gameover {
+        if self.simulation.is_over() {
             match keycode {
-                KeyCode::Return => self.restart(),
+                // Every new game gets a fresh seed of its own.
+                KeyCode::Return => self.restart(rand::random()),
                 KeyCode::Escape => event::quit(_ctx),
+                KeyCode::H => {
+                    let score =
+                        Score::new(&self.simulation, &SNAKE_CONFIG.get(), self.level.as_ref());
+                    self.screen = Screen::HighScores(score);
+                }
                 _ => {}
             }
         }
     }
+
+    /// text_input_event gets fired for each character typed, which we use to
+    /// let the player type their name in for a high score.
+    fn text_input_event(&mut self, _ctx: &mut Context, character: char) {
+        if let Screen::EnterName(score) = &mut self.screen {
+            if (character.is_alphanumeric() || character == ' ')
+                && score.name.chars().count() < MAX_NAME_LENGTH
+            {
+                score.name.push(character);
+            }
+        }
+    }
+
+    /// quit_event gets fired when the window is closed. If the game was still going
+    /// we save it as it stands, since it won't get the chance to finish.
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        if self.playback.is_none() && !self.simulation.is_over() {
+            self.save_recording();
+        }
+        false
+    }
+}
+
+/// Whether to show how long frames are taking from the start, which
+/// `--frame-time` or `show_frame_time` in the config asks for.
+fn frame_time() -> Option<FrameTime> {
+    if SNAKE_CONFIG.get().show_frame_time || CLI.frame_time {
+        Some(FrameTime::default())
+    } else {
+        None
+    }
+}
+
+/// Whether to draw the snakes moving smoothly between ticks, which they do
+/// unless `smooth_movement` in the config asks for the classic stepped look.
+fn motion() -> Option<Motion> {
+    if SNAKE_CONFIG.get().smooth_movement {
+        Some(Motion::default())
+    } else {
+        None
+    }
+}
+
+/// We also create a helper function that will let us convert between a
+/// `ggez` `Keycode` and the `Direction` that it represents. Of course,
+/// not every keycode represents a direction, so we return `None` if this
+/// is the case.
+fn arrow_direction(key: KeyCode) -> Option<Direction> {
+    match key {
+        KeyCode::Up => Some(Direction::Up),
+        KeyCode::Down => Some(Direction::Down),
+        KeyCode::Left => Some(Direction::Left),
+        KeyCode::Right => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+/// The same again for the W, A, S and D keys, which the second player
+/// steers with in a versus game.
+fn wasd_direction(key: KeyCode) -> Option<Direction> {
+    match key {
+        KeyCode::W => Some(Direction::Up),
+        KeyCode::S => Some(Direction::Down),
+        KeyCode::A => Some(Direction::Left),
+        KeyCode::D => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+/// Something that changes whenever anything drawn on the board does: the
+/// checksum covers the snakes and the food, and the walls only change along
+/// with the level.
+fn board_version(simulation: &Simulation) -> u64 {
+    let board = simulation.board();
+    let walls = board.walls.len() as u64;
+    simulation.checksum() ^ (walls << 32 | (board.width as u64) << 16 | board.height as u64)
+}
+
+/// The longest name we'll put in the high score table.
+const MAX_NAME_LENGTH: usize = 12;
+
+/// Ask the player to type in their name for the high score they just set.
+fn draw_enter_name(score: &Score, ctx: &mut Context) -> GameResult {
+    let record_text = format!("New High Score {}!", score.score);
+    draw_text(1, record_text, ctx)?;
+
+    let name_text = format!("Your Name: {}_", score.name);
+    draw_text(2, name_text, ctx)?;
+
+    draw_text(3, String::from("Press Enter When Done."), ctx)
+}
+
+/// Show the best scores for the board size and speed `score` was played at.
+fn draw_high_scores(high_scores: &HighScores, score: &Score, ctx: &mut Context) -> GameResult {
+    let title = format!(
+        "High Scores, {}x{} at {} Moves a Second",
+        score.grid_width, score.grid_height, score.updates_per_second
+    );
+    draw_text(1, title, ctx)?;
+
+    let table = high_scores.table(score);
+    if table.is_empty() {
+        draw_text(3, String::from("No Scores Yet."), ctx)?;
+    }
+    for (rank, entry) in table.iter().enumerate() {
+        let line = format!(
+            "{:>2}. {:<12} {:>4} long {:>4} {:>3}:{:02} {}",
+            rank + 1,
+            entry.name,
+            entry.score,
+            entry.length,
+            entry.duration as u64 / 60,
+            entry.duration as u64 % 60,
+            entry.day()
+        );
+        draw_text(rank as u16 + 3, line, ctx)?;
+    }
+
+    let footer_row = HighScores::TABLE_SIZE as u16 + 4;
+    draw_text(
+        footer_row,
+        String::from("Enter to Play Again, H to Go Back, Escape to Exit."),
+        ctx,
+    )
+}
+
+/// Let the player choose which of the stages they have unlocked to play.
+fn draw_stage_select(run: &CampaignRun, ctx: &mut Context) -> GameResult {
+    draw_text(1, run.campaign.name.clone(), ctx)?;
+
+    let stage = &run.campaign.stages[run.stage];
+    let stage_text = format!(
+        "Stage {} of {}: {}",
+        run.stage + 1,
+        run.campaign.stages.len(),
+        stage.name()
+    );
+    draw_text(3, stage_text, ctx)?;
+    draw_text(4, stage.goal.describe(), ctx)?;
+
+    let unlocked_text = format!(
+        "{} of {} Stages Unlocked.",
+        run.progress.unlocked(&run.campaign),
+        run.campaign.stages.len()
+    );
+    draw_text(6, unlocked_text, ctx)?;
+
+    draw_text(
+        8,
+        String::from("Left and Right to Choose, Enter to Play."),
+        ctx,
+    )
+}
+
+/// Congratulate the player on finishing a stage and tell them what's next.
+fn draw_stage_complete(run: &CampaignRun, ctx: &mut Context) -> GameResult {
+    let (title, prompt) = if run.last_stage() {
+        ("Campaign Complete!", "Press Enter to Choose a Stage.")
+    } else {
+        ("Stage Complete!", "Press Enter for the Next Stage.")
+    };
+    draw_text(1, String::from(title), ctx)?;
+
+    let total_text = format!("Total Score {}.", run.total_score);
+    draw_text(2, total_text, ctx)?;
+
+    if !run.last_stage() {
+        let next = &run.campaign.stages[run.stage + 1];
+        let next_text = format!("Next: {}, {}.", next.name(), next.goal.describe());
+        draw_text(4, next_text, ctx)?;
+    }
+
+    draw_text(5, String::from(prompt), ctx)
+}
+
+/// Show how the versus match is going: every player's score and rounds won while
+/// a round is being played, and who won once it is over.
+fn draw_versus(
+    versus: &Match,
+    simulation: &Simulation,
+    screen: &Screen,
+    ctx: &mut Context,
+) -> GameResult {
+    let scores: Vec<String> = (0..simulation.players())
+        .map(|player| {
+            format!(
+                "P{} {} ({} Won)",
+                player + 1,
+                simulation.score_of(player),
+                versus.wins[player]
+            )
+        })
+        .collect();
+    let status = format!("Round {}  {}", versus.round, scores.join("  "));
+    draw_text(0, status, ctx)?;
+
+    let (title, prompt) = match screen {
+        Screen::RoundOver(Some(winner)) => (
+            format!("Player {} Wins Round {}!", winner + 1, versus.round),
+            "Press Enter for the Next Round.",
+        ),
+        Screen::RoundOver(None) => (
+            format!("Round {} is a Draw!", versus.round),
+            "Press Enter for the Next Round.",
+        ),
+        Screen::MatchOver(champion) => (
+            format!("Player {} Wins the Match!", champion + 1),
+            "Press Enter for a New Match.",
+        ),
+        _ => return Ok(()),
+    };
+    draw_text(2, title, ctx)?;
+    draw_text(3, String::from(prompt), ctx)?;
+    draw_text(4, String::from("Press Escape to Exit."), ctx)
+}
+
+/// Show who we are in a networked game, who we're waiting for if anyone, and
+/// how it ended once it has.
+fn draw_network(network: &Network, simulation: &Simulation, ctx: &mut Context) -> GameResult {
+    let player = network.session.player();
+    let status = format!(
+        "You are Player {} of {}, Score {}",
+        player + 1,
+        simulation.players(),
+        simulation.score_of(player)
+    );
+    draw_text(0, status, ctx)?;
+
+    let title = if let Some(error) = &network.error {
+        error.clone()
+    } else if network.session.is_settled(simulation) {
+        match simulation.winner() {
+            Some(winner) if winner == player => String::from("You Win!"),
+            Some(winner) => format!("Player {} Wins!", winner + 1),
+            None => String::from("It's a Draw!"),
+        }
+    } else {
+        let waiting = network.session.waiting_on(simulation);
+        if waiting.is_empty() {
+            return Ok(());
+        }
+        let players: Vec<String> = waiting.iter().map(|p| (p + 1).to_string()).collect();
+        return draw_text(
+            2,
+            format!("Waiting for Player {}...", players.join(", ")),
+            ctx,
+        );
+    };
+    draw_text(2, title, ctx)?;
+    draw_text(3, String::from("Press Escape to Exit."), ctx)
 }
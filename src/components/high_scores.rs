@@ -0,0 +1,216 @@
+use crate::components::board::Boundary;
+use crate::components::level::Level;
+use crate::components::simulation::Simulation;
+use crate::{data_file, SnakeConfig};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One finished game worth remembering.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Score {
+    pub name: String,
+    pub score: usize,
+    /// The length of the snake, head included.
+    pub length: usize,
+    /// How long the game lasted in seconds of game time.
+    pub duration: f32,
+    pub grid_width: u16,
+    pub grid_height: u16,
+    pub updates_per_second: f32,
+    /// Games against walls aren't comparable with games that wrap around.
+    #[serde(default)]
+    pub boundary: Boundary,
+    /// The name of the level played, if any.
+    #[serde(default)]
+    pub level: Option<String>,
+    /// When the game was played, in seconds since the Unix epoch.
+    pub date: u64,
+}
+
+impl Score {
+    /// The score for the game `simulation` played under `config` on `level`. The
+    /// name is left blank until the player tells us who they are.
+    pub fn new(simulation: &Simulation, config: &SnakeConfig, level: Option<&Level>) -> Self {
+        Score {
+            name: String::new(),
+            score: simulation.score(),
+            length: simulation.snake().len(),
+            duration: simulation.ticks() as f32 / config.updates_per_second,
+            grid_width: config.grid_width,
+            grid_height: config.grid_height,
+            updates_per_second: config.updates_per_second,
+            boundary: config.boundary,
+            level: level.map(|level| level.name.clone()),
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        }
+    }
+
+    /// Scores are only comparable with other games played on the same size board
+    /// at the same speed with the same kind of edges and the same level.
+    pub fn same_bucket(&self, other: &Score) -> bool {
+        self.grid_width == other.grid_width
+            && self.grid_height == other.grid_height
+            && self.updates_per_second == other.updates_per_second
+            && self.boundary == other.boundary
+            && self.level == other.level
+    }
+
+    /// The date the game was played on, as `YYYY-MM-DD`.
+    pub fn day(&self) -> String {
+        // Count days from 1 March of year 0 so that leap days fall at the end of each year.
+        let days = (self.date / 86_400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 {
+            month_from_march + 3
+        } else {
+            month_from_march - 9
+        };
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+/// The best games played on this machine, kept in a file in the user's data directory.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct HighScores {
+    scores: Vec<Score>,
+}
+
+impl HighScores {
+    /// How many scores are kept for each board size and speed.
+    pub const TABLE_SIZE: usize = 10;
+
+    /// Where the high scores are kept, if the platform gives us somewhere to keep them.
+    pub fn path() -> Option<PathBuf> {
+        data_file("high_scores.json")
+    }
+
+    /// Read the high scores from disk. If there aren't any yet we start with an empty table.
+    pub fn load() -> Self {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return HighScores::default(),
+        };
+        let file = match File::open(&path) {
+            Ok(file) => file,
+            Err(_) => return HighScores::default(),
+        };
+        serde_json::from_reader(BufReader::new(file)).unwrap_or_else(|e| {
+            eprintln!(
+                "Ignoring unreadable high scores in {}: {}",
+                path.display(),
+                e
+            );
+            HighScores::default()
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "No data directory to save to")
+        })?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        Ok(serde_json::to_writer_pretty(BufWriter::new(file), self)?)
+    }
+
+    /// The best scores played on the same board size and speed as `score`, best first.
+    pub fn table(&self, score: &Score) -> Vec<&Score> {
+        let mut table: Vec<&Score> = self
+            .scores
+            .iter()
+            .filter(|other| other.same_bucket(score))
+            .collect();
+        // Ties go to whoever got there first.
+        table.sort_by(|a, b| b.score.cmp(&a.score).then(a.date.cmp(&b.date)));
+        table.truncate(Self::TABLE_SIZE);
+        table
+    }
+
+    /// Every table there are scores for, one for each board size and speed
+    /// that has been played, in the order they were first played.
+    pub fn tables(&self) -> Vec<Vec<&Score>> {
+        let mut tables: Vec<Vec<&Score>> = Vec::new();
+        for score in &self.scores {
+            if !tables.iter().any(|table| table[0].same_bucket(score)) {
+                tables.push(self.table(score));
+            }
+        }
+        tables
+    }
+
+    /// Whether `score` is good enough to make it into its table. A game that
+    /// scored nothing never is, however empty the table.
+    pub fn qualifies(&self, score: &Score) -> bool {
+        let table = self.table(score);
+        score.score > 0
+            && (table.len() < Self::TABLE_SIZE
+                || table.iter().any(|other| score.score > other.score))
+    }
+
+    /// Add `score` to the table, dropping whichever score it knocks out.
+    pub fn insert(&mut self, score: Score) {
+        self.scores.push(score.clone());
+        let keep: Vec<Score> = self.table(&score).into_iter().cloned().collect();
+        self.scores.retain(|other| !other.same_bucket(&score));
+        self.scores.extend(keep);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn played_on(date: u64) -> Score {
+        Score {
+            name: String::from("Ada"),
+            score: 10,
+            length: 11,
+            duration: 12.5,
+            grid_width: 30,
+            grid_height: 30,
+            updates_per_second: 8.0,
+            boundary: Boundary::Wrap,
+            level: None,
+            date,
+        }
+    }
+
+    #[test]
+    fn day_gives_the_calendar_date() {
+        assert_eq!(played_on(0).day(), "1970-01-01");
+        assert_eq!(played_on(86_399).day(), "1970-01-01");
+        assert_eq!(played_on(86_400).day(), "1970-01-02");
+        // Leap days, including the century that was a leap year.
+        assert_eq!(played_on(951_782_400).day(), "2000-02-29");
+        assert_eq!(played_on(1_709_164_800).day(), "2024-02-29");
+        assert_eq!(played_on(1_709_251_200).day(), "2024-03-01");
+        assert_eq!(played_on(1_735_689_599).day(), "2024-12-31");
+    }
+
+    #[test]
+    fn a_score_of_nothing_never_qualifies() {
+        let mut high_scores = HighScores::default();
+        let mut score = played_on(0);
+        score.score = 0;
+        assert!(!high_scores.qualifies(&score));
+
+        score.score = 1;
+        assert!(high_scores.qualifies(&score));
+        high_scores.insert(score);
+        assert!(high_scores.qualifies(&played_on(0)));
+    }
+}
@@ -0,0 +1,116 @@
+use crate::components::board::Board;
+use crate::components::direction::Direction;
+use crate::components::grid_position::GridPosition;
+use crate::SnakeConfig;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// A `Level` lays out the terrain of a board. It is written as a TOML file with
+/// a `name` and a `map`, where the map is ASCII art with one character per cell:
+///
+/// ```text
+/// #   a wall
+/// .   an empty cell
+/// *   an empty cell that food is placed on, if any of them are free
+/// >   where the snake's head starts, moving right. `<`, `^` and `v` start it
+///     moving left, up and down.
+/// ```
+///
+/// The snake's first body segment starts just behind its head, so that cell
+/// must be free too. A map can have several spawns for games with more than
+/// one snake; they are handed out left to right, top to bottom.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Level {
+    pub name: String,
+    pub map: String,
+}
+
+impl Level {
+    /// Read a level from `path` and check that it can be played with `config`.
+    pub fn load(path: &Path, config: &SnakeConfig) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let level: Level = toml::from_str(&text).map_err(|e| e.to_string())?;
+        level.board(config)?;
+        Ok(level)
+    }
+
+    /// Build the board this level describes, with the size and boundary from `config`.
+    pub fn board(&self, config: &SnakeConfig) -> Result<Board, String> {
+        let mut board = Board::new(config.grid_width, config.grid_height, config.boundary);
+        let rows: Vec<&str> = self
+            .map
+            .lines()
+            .map(str::trim_end)
+            .filter(|row| !row.is_empty())
+            .collect();
+        if rows.len() != config.grid_height as usize {
+            return Err(format!(
+                "The map is {} rows tall but grid_height is {}",
+                rows.len(),
+                config.grid_height
+            ));
+        }
+
+        board.spawns.clear();
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != config.grid_width as usize {
+                return Err(format!(
+                    "Row {} of the map is {} cells wide but grid_width is {}",
+                    y + 1,
+                    row.chars().count(),
+                    config.grid_width
+                ));
+            }
+            for (x, cell) in row.chars().enumerate() {
+                let pos = GridPosition::new(x as u16, y as u16);
+                let dir = match cell {
+                    '#' => {
+                        board.walls.insert(pos);
+                        None
+                    }
+                    '*' => {
+                        board.food_zones.push(pos);
+                        None
+                    }
+                    '.' => None,
+                    '>' => Some(Direction::Right),
+                    '<' => Some(Direction::Left),
+                    '^' => Some(Direction::Up),
+                    'v' => Some(Direction::Down),
+                    _ => {
+                        return Err(format!(
+                            "Unknown cell '{}' at row {}, column {} of the map",
+                            cell,
+                            y + 1,
+                            x + 1
+                        ))
+                    }
+                };
+                if let Some(dir) = dir {
+                    board.spawns.push((pos, dir));
+                }
+            }
+        }
+
+        if board.spawns.is_empty() {
+            return Err(String::from("The map has no snake spawn (one of > < ^ v)"));
+        }
+        for &(pos, dir) in &board.spawns {
+            match board.step(pos, dir.inverse()) {
+                Some(tail) if !board.is_wall(tail) => {}
+                _ => {
+                    return Err(format!(
+                        "The cell behind the snake spawn at row {}, column {} must be free for its first body segment",
+                        pos.y + 1,
+                        pos.x + 1
+                    ))
+                }
+            }
+        }
+        if board.walls.len() + 2 >= board.cells() {
+            return Err(String::from("The map leaves no room for food"));
+        }
+        Ok(board)
+    }
+}
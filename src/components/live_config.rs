@@ -0,0 +1,101 @@
+use crate::SnakeConfig;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+
+/// The config the game is running with, which can be swapped for another while
+/// it runs. Whoever wants to read it gets the one in use with `get`, and keeps
+/// it for as long as they like, even if it is replaced in the meantime. A frame
+/// takes one and draws everything with it.
+pub struct LiveConfig {
+    current: RwLock<Arc<SnakeConfig>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: SnakeConfig) -> Self {
+        LiveConfig {
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    /// The config in use right now.
+    pub fn get(&self) -> Arc<SnakeConfig> {
+        self.current
+            .read()
+            .expect("Nobody panics while replacing the config")
+            .clone()
+    }
+
+    /// Use `config` from now on.
+    pub fn replace(&self, config: SnakeConfig) {
+        *self
+            .current
+            .write()
+            .expect("Nobody panics while replacing the config") = Arc::new(config);
+    }
+}
+
+/// Keeps an eye on a config file to tell when it has been saved. We look at
+/// when it was last modified every so often, which is plenty to notice an
+/// edit made in another window.
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl ConfigWatcher {
+    const CHECK_EVERY: Duration = Duration::from_millis(500);
+
+    /// Watch the file at `path`, which counts as unchanged as it is now.
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified(&path);
+        ConfigWatcher {
+            path,
+            modified,
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Whether the file has been saved since we last asked.
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.elapsed() < Self::CHECK_EVERY {
+            return false;
+        }
+        self.last_check = Instant::now();
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_config_in_use_outlives_its_replacement() {
+        let live = LiveConfig::new(crate::default_config());
+        let before = live.get();
+        let mut config = crate::default_config();
+        config.updates_per_second = before.updates_per_second * 2.0;
+        live.replace(config);
+
+        assert_eq!(
+            live.get().updates_per_second,
+            before.updates_per_second * 2.0
+        );
+        assert_eq!(
+            before.updates_per_second,
+            crate::default_config().updates_per_second
+        );
+    }
+}
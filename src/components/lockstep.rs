@@ -0,0 +1,537 @@
+use crate::components::direction::Direction;
+use crate::components::netcode::{Netcode, Setup};
+use crate::components::replay::Replay;
+use crate::components::simulation::Simulation;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// What the machines in a networked game say to each other, one JSON object per
+/// line over TCP. Players only ever talk to the host, which passes on whatever
+/// one of them says to all the others.
+#[derive(Debug, Serialize, Deserialize)]
+enum Message {
+    /// From the host to a player who joined: which player they are, and the game.
+    Welcome { player: usize, setup: Setup },
+    /// The keys `player` pressed that take effect just before tick `tick`, along
+    /// with the checksum of their game as it was `input_delay` ticks earlier,
+    /// when they sent it.
+    Input {
+        player: usize,
+        tick: u64,
+        dirs: Vec<Direction>,
+        checksum: u64,
+    },
+    /// From the host: `player` has left, and from `tick` on their snake carries
+    /// on without anyone steering it.
+    Dropped { player: usize, tick: u64 },
+}
+
+/// What the host knows about a player who joined it.
+struct Peer {
+    /// Where we write to them, until they leave.
+    stream: Option<TcpStream>,
+    /// The first tick we don't have their input for yet.
+    next_tick: u64,
+    last_heard: Instant,
+}
+
+/// Which side of the connection we are on.
+enum Link {
+    /// The host keeps a connection to every other player, indexed by player, so
+    /// the host's own entry is empty.
+    Host {
+        peers: Vec<Option<Peer>>,
+        /// How long a player can keep everyone waiting before they're dropped.
+        timeout: Duration,
+    },
+    Client {
+        stream: TcpStream,
+    },
+}
+
+/// A networked game in deterministic lockstep. Every machine runs its own copy
+/// of the `Simulation` from the same seed, and only the keys each player presses
+/// are sent around. A tick is only simulated once everyone's keys for it have
+/// arrived, so every copy sees exactly the same inputs in the same order and
+/// plays out exactly the same way.
+///
+/// Keys take effect `input_delay` ticks after they are pressed, which gives
+/// them time to travel. To catch copies that drift apart anyway, every input
+/// carries a checksum of the sender's game, which everyone compares against
+/// their own.
+pub struct Lockstep {
+    player: usize,
+    setup: Setup,
+    link: Link,
+    /// Messages from the threads reading each connection, tagged with the
+    /// player they came from. `None` means the connection closed.
+    messages: Receiver<(usize, Option<Message>)>,
+    /// Everyone's inputs for the ticks still to come.
+    inputs: HashMap<u64, Vec<Option<Vec<Direction>>>>,
+    /// The keys the local player pressed since their last input was sent.
+    pressed: Vec<Direction>,
+    /// The tick the local player's next input is for.
+    next_input: u64,
+    /// The tick each player left on, if they have.
+    dropped: Vec<Option<u64>>,
+    /// Our own checksums for recent ticks, and those of other players we
+    /// haven't got to yet.
+    checksums: BTreeMap<u64, u64>,
+    unchecked: Vec<(u64, usize, u64)>,
+    /// Whether the host has gone. We can still play the ticks we already
+    /// have everyone's inputs for.
+    host_left: bool,
+}
+
+impl Lockstep {
+    /// How many ticks of our own checksums we keep to compare with slower players.
+    const CHECKSUM_HISTORY: u64 = 1024;
+
+    /// Host a game for `setup.players` players on `port`, waiting until all the
+    /// others have joined. Players who keep everyone waiting for longer than
+    /// `timeout` are dropped.
+    pub fn host(port: u16, setup: Setup, timeout: Duration) -> io::Result<Self> {
+        Lockstep::host_on(TcpListener::bind(("0.0.0.0", port))?, setup, timeout)
+    }
+
+    /// Host a game the same way, with the players joining through `listener`.
+    fn host_on(listener: TcpListener, setup: Setup, timeout: Duration) -> io::Result<Self> {
+        let port = listener.local_addr()?.port();
+        let (sender, messages) = mpsc::channel();
+        let mut peers = vec![None];
+        for player in 1..setup.players {
+            println!(
+                "Waiting for {} more player(s) on port {}",
+                setup.players - player,
+                port
+            );
+            let (stream, address) = listener.accept()?;
+            println!("Player {} joined from {}", player + 1, address);
+            stream.set_nodelay(true)?;
+            listen(stream.try_clone()?, player, sender.clone());
+            peers.push(Some(Peer {
+                stream: Some(stream),
+                next_tick: setup.input_delay,
+                last_heard: Instant::now(),
+            }));
+        }
+
+        let mut session = Lockstep::new(0, setup, Link::Host { peers, timeout }, messages);
+        // Nobody says anything until everyone is here, so that every game starts together.
+        for player in 1..session.setup.players {
+            let welcome = Message::Welcome {
+                player,
+                setup: session.setup.clone(),
+            };
+            session.send_to(player, &welcome);
+        }
+        Ok(session)
+    }
+
+    /// Join the game hosted at `address`, such as `192.168.1.20:7777`, waiting
+    /// until everyone else has joined too.
+    pub fn join(address: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        let (sender, messages) = mpsc::channel();
+        listen(stream.try_clone()?, 0, sender);
+        println!("Connected to {}, waiting for the game to start", address);
+        match messages.recv() {
+            Ok((_, Some(Message::Welcome { player, setup }))) => Ok(Lockstep::new(
+                player,
+                setup,
+                Link::Client { stream },
+                messages,
+            )),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The host didn't welcome us to the game",
+            )),
+        }
+    }
+
+    fn new(
+        player: usize,
+        setup: Setup,
+        link: Link,
+        messages: Receiver<(usize, Option<Message>)>,
+    ) -> Self {
+        Lockstep {
+            player,
+            next_input: setup.input_delay,
+            dropped: vec![None; setup.players],
+            setup,
+            link,
+            messages,
+            inputs: HashMap::new(),
+            pressed: Vec::new(),
+            checksums: BTreeMap::new(),
+            unchecked: Vec::new(),
+            host_left: false,
+        }
+    }
+
+    /// Handle everything that has arrived since we last looked, and on the host
+    /// drop anyone who has kept us waiting too long.
+    fn poll(&mut self) -> Result<(), String> {
+        loop {
+            match self.messages.try_recv() {
+                Ok((from, Some(message))) => self.receive(from, message)?,
+                Ok((from, None)) => self.disconnected(from)?,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return self.disconnected(0),
+            }
+        }
+
+        if let Link::Host { peers, timeout } = &self.link {
+            // The tick we are on, whose input has already been sent.
+            let tick = self.next_input.saturating_sub(self.setup.input_delay + 1);
+            let late: Vec<usize> = (0..peers.len())
+                .filter(|&player| {
+                    peers[player].as_ref().is_some_and(|peer| {
+                        peer.stream.is_some()
+                            && peer.next_tick <= tick
+                            && peer.last_heard.elapsed() > *timeout
+                    })
+                })
+                .collect();
+            for player in late {
+                eprintln!("Player {} stopped answering", player + 1);
+                self.disconnected(player)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deal with a message from `from`: remember an input and pass it on to
+    /// everyone else if we are the host, or note that someone left.
+    ///
+    /// The host hears from every player directly, so it only takes a player's
+    /// input from that player, and only takes its own word that someone left.
+    fn receive(&mut self, from: usize, message: Message) -> Result<(), String> {
+        let host = self.is_host();
+        match message {
+            Message::Input {
+                player,
+                tick,
+                ref dirs,
+                checksum,
+            } if player < self.setup.players && (!host || player == from) => {
+                let players = self.setup.players;
+                self.inputs
+                    .entry(tick)
+                    .or_insert_with(|| vec![None; players])[player] = Some(dirs.clone());
+                // Inputs are never for a tick before `input_delay`, but one
+                // that claims to be has no checksum we can compare.
+                if let Some(sent) = tick.checked_sub(self.setup.input_delay) {
+                    if player != self.player {
+                        self.unchecked.push((sent, player, checksum));
+                    }
+                }
+                if let Link::Host { peers, .. } = &mut self.link {
+                    if let Some(Some(peer)) = peers.get_mut(from) {
+                        peer.next_tick = tick + 1;
+                        peer.last_heard = Instant::now();
+                    }
+                }
+                // The host passes every input on, and a player sends their own to the host.
+                if self.is_host() || from == self.player {
+                    self.broadcast(from, &message);
+                }
+                self.check()
+            }
+            Message::Dropped { player, tick }
+                if player < self.setup.players && (!host || from == self.player) =>
+            {
+                eprintln!("Player {} left the game", player + 1);
+                self.dropped[player] = Some(tick);
+                Ok(())
+            }
+            message => {
+                eprintln!("Ignoring a message we didn't expect: {:?}", message);
+                Ok(())
+            }
+        }
+    }
+
+    /// Compare the checksums other players sent with our own for the same tick.
+    fn check(&mut self) -> Result<(), String> {
+        let checksums = &self.checksums;
+        let mut desync = None;
+        self.unchecked
+            .retain(|&(tick, player, checksum)| match checksums.get(&tick) {
+                Some(&ours) => {
+                    if ours != checksum {
+                        desync = Some((tick, player));
+                    }
+                    false
+                }
+                // Ticks we no longer have a checksum of our own for can't be checked.
+                None => checksums.keys().next().is_none_or(|&oldest| tick > oldest),
+            });
+        match desync {
+            Some((tick, player)) => Err(format!(
+                "Out of sync with Player {} at tick {}",
+                player + 1,
+                tick
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Someone's connection closed. If it was the host the game is over for us
+    /// once we run out of inputs they passed on.
+    /// If we are the host we tell everyone else which tick the player left on:
+    /// the first one we don't have their input for, since every input of theirs
+    /// before it has already been passed on.
+    fn disconnected(&mut self, player: usize) -> Result<(), String> {
+        let tick = match &mut self.link {
+            Link::Client { .. } => {
+                self.host_left = true;
+                return Ok(());
+            }
+            Link::Host { peers, .. } => match peers.get_mut(player) {
+                Some(Some(peer)) if peer.stream.is_some() => {
+                    if let Some(stream) = peer.stream.take() {
+                        let _ = stream.shutdown(Shutdown::Both);
+                    }
+                    peer.next_tick
+                }
+                _ => return Ok(()),
+            },
+        };
+        let dropped = Message::Dropped { player, tick };
+        self.broadcast(player, &dropped);
+        self.receive(0, dropped)
+    }
+
+    fn is_host(&self) -> bool {
+        matches!(self.link, Link::Host { .. })
+    }
+
+    /// Send `message` on to every player but `from`, or to the host if we are
+    /// a client sending our own input.
+    fn broadcast(&mut self, from: usize, message: &Message) {
+        match &self.link {
+            Link::Host { peers, .. } => {
+                for player in 1..peers.len() {
+                    if player != from {
+                        self.send_to(player, message);
+                    }
+                }
+            }
+            Link::Client { .. } => self.send_to(0, message),
+        }
+    }
+
+    /// Write `message` to `player`. A player we can't write to any more is left
+    /// for their reading thread to notice.
+    fn send_to(&mut self, player: usize, message: &Message) {
+        let line = serde_json::to_string(message).expect("Messages can always be written as JSON");
+        let stream = match &mut self.link {
+            Link::Host { peers, .. } => match peers.get_mut(player) {
+                Some(Some(peer)) => peer.stream.as_mut(),
+                _ => None,
+            },
+            Link::Client { stream } => Some(stream),
+        };
+        if let Some(stream) = stream {
+            if let Err(e) = writeln!(stream, "{}", line) {
+                eprintln!("Couldn't send to Player {}: {}", player + 1, e);
+            }
+        }
+    }
+}
+
+impl Netcode for Lockstep {
+    fn player(&self) -> usize {
+        self.player
+    }
+
+    fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    /// Whether we still have to send the local player's input for the tick
+    /// `input_delay` ticks after the one `simulation` is on.
+    fn needs_input(&self, simulation: &Simulation) -> bool {
+        self.next_input <= simulation.ticks() + self.setup.input_delay
+    }
+
+    /// Steer the local player's snake towards `dir`. It turns once the input
+    /// carrying it has been through everyone, `input_delay` ticks from now.
+    fn steer(&mut self, dir: Direction) {
+        self.pressed.push(dir);
+    }
+
+    fn waiting_on(&self, simulation: &Simulation) -> Vec<usize> {
+        let tick = simulation.ticks();
+        if tick < self.setup.input_delay {
+            return Vec::new();
+        }
+        (0..self.setup.players)
+            .filter(|&player| {
+                let arrived = self
+                    .inputs
+                    .get(&tick)
+                    .is_some_and(|inputs| inputs[player].is_some());
+                let left = self.dropped[player].is_some_and(|dropped| dropped <= tick);
+                !arrived && !left
+            })
+            .collect()
+    }
+
+    /// Send the local player's input if it is due, read everything the others
+    /// sent, and if everyone's inputs for the next tick are in, step
+    /// `simulation` with them, recording them in `replay`. Gives whether the
+    /// game was stepped, or an error if it can't go on: the host left, or our
+    /// game no longer matches someone else's.
+    fn tick(&mut self, simulation: &mut Simulation, replay: &mut Replay) -> Result<bool, String> {
+        if self.needs_input(simulation) {
+            let input = Message::Input {
+                player: self.player,
+                tick: self.next_input,
+                dirs: std::mem::take(&mut self.pressed),
+                checksum: simulation.checksum(),
+            };
+            self.checksums
+                .insert(simulation.ticks(), simulation.checksum());
+            self.checksums = self.checksums.split_off(
+                &simulation
+                    .ticks()
+                    .saturating_sub(Lockstep::CHECKSUM_HISTORY),
+            );
+            self.receive(self.player, input)?;
+            self.next_input += 1;
+        }
+        self.poll()?;
+
+        if !self.waiting_on(simulation).is_empty() {
+            if self.host_left {
+                return Err(String::from("The host left the game"));
+            }
+            return Ok(false);
+        }
+        if let Some(inputs) = self.inputs.remove(&simulation.ticks()) {
+            for (player, dirs) in inputs.into_iter().enumerate() {
+                for dir in dirs.unwrap_or_default() {
+                    replay.record(simulation, player, dir);
+                    simulation.steer_player(player, dir);
+                }
+            }
+        }
+        simulation.step(None);
+        Ok(true)
+    }
+}
+
+/// Read messages from `stream` on a thread of its own, passing them to `sender`
+/// tagged with the `player` at the other end, and `None` once it closes.
+fn listen(stream: TcpStream, player: usize, sender: Sender<(usize, Option<Message>)>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines().map_while(Result::ok) {
+            match serde_json::from_str(&line) {
+                Ok(message) => {
+                    if sender.send((player, Some(message))).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => eprintln!("Player {} sent something we can't read: {}", player + 1, e),
+            }
+        }
+        let _ = sender.send((player, None));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::board::{Board, Boundary};
+    use crate::components::controller::{Controller, Pathfinder};
+
+    /// Play the game of `session` to the end with a bot steering our snake,
+    /// giving the checksum and tick it ended on.
+    fn play(mut session: Lockstep) -> Result<(u64, u64), String> {
+        let setup = session.setup().clone();
+        let board = Board::new(setup.grid_width, setup.grid_height, setup.boundary);
+        let mut simulation = Simulation::with_players(board, setup.players, setup.seed);
+        let mut replay = Replay::new(setup.seed, crate::default_config(), None, setup.players);
+        let player = session.player();
+        let started = Instant::now();
+        while !session.is_settled(&simulation) && simulation.ticks() < 300 {
+            if started.elapsed() > Duration::from_secs(30) {
+                return Err(format!("Player {} never finished", player + 1));
+            }
+            if session.needs_input(&simulation) && simulation.snakes()[player].is_alive() {
+                session.steer(Pathfinder.next_dir(&simulation, player));
+            }
+            if !session.tick(&mut simulation, &mut replay)? {
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+        Ok((simulation.checksum(), simulation.ticks()))
+    }
+
+    fn setup() -> Setup {
+        Setup {
+            seed: 42,
+            players: 3,
+            input_delay: 2,
+            grid_width: 16,
+            grid_height: 12,
+            boundary: Boundary::Wrap,
+            updates_per_second: 8.0,
+            level: None,
+        }
+    }
+
+    #[test]
+    fn the_host_only_takes_a_players_input_from_that_player() {
+        let (_, messages) = mpsc::channel();
+        let link = Link::Host {
+            peers: vec![None, None, None],
+            timeout: Duration::from_secs(1),
+        };
+        let mut host = Lockstep::new(0, setup(), link, messages);
+        let input = |player| Message::Input {
+            player,
+            tick: 5,
+            dirs: vec![Direction::Up],
+            checksum: 0,
+        };
+
+        host.receive(1, input(2)).unwrap();
+        host.receive(1, Message::Dropped { player: 2, tick: 3 })
+            .unwrap();
+        assert!(host.inputs.is_empty());
+        assert_eq!(host.dropped[2], None);
+
+        host.receive(1, input(1)).unwrap();
+        assert_eq!(host.inputs[&5][1], Some(vec![Direction::Up]));
+    }
+
+    #[test]
+    fn everyone_plays_the_same_game() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let joining: Vec<_> = (0..2)
+            .map(|_| {
+                let address = address.clone();
+                thread::spawn(move || play(Lockstep::join(&address).unwrap()))
+            })
+            .collect();
+        let host = Lockstep::host_on(listener, setup(), Duration::from_secs(10)).unwrap();
+        let hosted = play(host).unwrap();
+        assert!(hosted.1 > 2, "the game ended before anyone's input counted");
+
+        for player in joining {
+            assert_eq!(player.join().unwrap().unwrap(), hosted);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use crate::components::draw::{cell_corner, Batch};
+use crate::components::grid_position::GridPosition;
+use crate::components::simulation::Simulation;
+use crate::components::skin::{tint, Ahead, Skin};
+use crate::components::snake::Snake;
+use crate::SnakeConfig;
+use ggez::mint::Point2;
+use std::time::{Duration, Instant};
+
+/// The game only moves a whole cell each tick, so drawn as it is the snakes
+/// jump from one cell to the next. To have them glide instead we remember
+/// where every snake's head and tail were the tick before the one being shown,
+/// and draw them sliding from there as the time to the next tick passes.
+///
+/// Everything between the head and the tail stays put over a tick, so that
+/// still goes in the board's cached mesh, and only the head and the tail are
+/// drawn again every frame.
+#[derive(Default)]
+pub struct Motion {
+    /// The tick being shown.
+    tick: Option<u64>,
+    /// Where each snake's head and tail are on that tick.
+    now: Vec<(GridPosition, GridPosition)>,
+    /// Where they were the tick before, which is the same as `now` if the
+    /// tick before wasn't seen, such as when a game has just started.
+    before: Vec<(GridPosition, GridPosition)>,
+    /// When we first saw the tick being shown.
+    since: Option<Instant>,
+}
+
+impl Motion {
+    /// Take note of the tick `simulation` is on, if it is a new one.
+    pub fn observe(&mut self, simulation: &Simulation) {
+        let tick = simulation.ticks();
+        if self.tick == Some(tick) {
+            return;
+        }
+        let now: Vec<(GridPosition, GridPosition)> = simulation.snakes().iter().map(ends).collect();
+        let follows_on = self.tick.map(|seen| seen + 1) == Some(tick);
+        self.before = if follows_on && self.now.len() == now.len() {
+            std::mem::take(&mut self.now)
+        } else {
+            now.clone()
+        };
+        self.now = now;
+        self.tick = Some(tick);
+        self.since = Some(Instant::now());
+    }
+
+    /// How far we are from the tick being shown to the next, from 0 to 1, when
+    /// ticks come every `interval`. If the next is late we wait where it'll be.
+    pub fn progress(&self, interval: Duration) -> f32 {
+        match self.since {
+            Some(since) if interval > Duration::from_secs(0) => {
+                (since.elapsed().as_secs_f32() / interval.as_secs_f32()).min(1.0)
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Draw the part of the snake of `player` that stays put over the tick:
+    /// everything but its head. If its tail is moving on, the cell it is
+    /// leaving isn't drawn either, but the last cell is drawn joined to it.
+    pub fn draw_still(&self, snake: &Snake, player: usize, skin: Option<&Skin>, batch: &mut Batch) {
+        let cells: Vec<GridPosition> = snake.positions().collect();
+        let left_behind = self.left_behind(snake, player);
+        let (_, body) = batch.config().snake_colors(player);
+        let tint = tint(player, batch.config());
+        for (i, &pos) in cells.iter().enumerate().skip(1) {
+            match skin {
+                Some(skin) => {
+                    let behind = cells.get(i + 1).copied().or(left_behind);
+                    skin.draw_piece(
+                        Ahead::Cell(cells[i - 1]),
+                        pos,
+                        behind,
+                        cell_corner(pos, batch.config()),
+                        tint,
+                        batch,
+                    );
+                }
+                None => batch.draw_rect(pos, body),
+            }
+        }
+    }
+
+    /// Draw the head of the snake of `player` `progress` of the way from where
+    /// it was to where it is, and its tail likewise if it is moving on.
+    pub fn draw_moving(
+        &self,
+        snake: &Snake,
+        player: usize,
+        skin: Option<&Skin>,
+        progress: f32,
+        batch: &mut Batch,
+    ) {
+        let (head_color, body_color) = batch.config().snake_colors(player);
+        let tint = tint(player, batch.config());
+        let (head, tail) = ends(snake);
+        let from = self.before.get(player).map_or(head, |&(from, _)| from);
+        let neck = snake.body.front().map(|seg| seg.pos);
+        let heading = Ahead::Heading(snake.dir);
+        for at in sliding(from, head, progress, batch.config()) {
+            match skin {
+                Some(skin) => skin.draw_piece(heading, head, neck, at, tint, batch),
+                None => batch.draw_rect_at(at, head_color),
+            }
+        }
+        if let Some(from) = self.left_behind(snake, player) {
+            for at in sliding(from, tail, progress, batch.config()) {
+                match skin {
+                    Some(skin) => skin.draw_piece(Ahead::Cell(tail), from, None, at, tint, batch),
+                    None => batch.draw_rect_at(at, body_color),
+                }
+            }
+        }
+    }
+
+    /// The cell the tail of the snake of `player` just left, if it moved on.
+    fn left_behind(&self, snake: &Snake, player: usize) -> Option<GridPosition> {
+        let (head, tail) = ends(snake);
+        match self.before.get(player) {
+            Some(&(from_head, from_tail)) if from_head != head && from_tail != tail => {
+                Some(from_tail)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Where the head and the tail of `snake` are.
+fn ends(snake: &Snake) -> (GridPosition, GridPosition) {
+    let tail = snake.body.back().map_or(snake.head.pos, |seg| seg.pos);
+    (snake.head.pos, tail)
+}
+
+/// Where, in pixels, something moving from the cell `from` to the cell `to`
+/// next to it is drawn `progress` of the way there. Going off the edge of a
+/// board that wraps it is drawn twice, on its way out of one side and on its
+/// way in at the other. The cells are as big as `config` says.
+fn sliding(
+    from: GridPosition,
+    to: GridPosition,
+    progress: f32,
+    config: &SnakeConfig,
+) -> Vec<Point2<f32>> {
+    // Cells on opposite edges are next to each other, so a step that looks to
+    // be most of the way across the board is really a single step back.
+    let step = |from: u16, to: u16| match to as i32 - from as i32 {
+        d if d > 1 => -1.0,
+        d if d < -1 => 1.0,
+        d => d as f32,
+    };
+    let (dx, dy) = (step(from.x, to.x), step(from.y, to.y));
+    let at = |pos: GridPosition, t: f32| {
+        let corner = cell_corner(pos, config);
+        Point2 {
+            x: corner.x + dx * t * config.cell_width as f32,
+            y: corner.y + dy * t * config.cell_height as f32,
+        }
+    };
+    let wrapped =
+        (to.x as i32 - from.x as i32).abs() > 1 || (to.y as i32 - from.y as i32).abs() > 1;
+    if wrapped {
+        vec![at(from, progress), at(to, progress - 1.0)]
+    } else {
+        vec![at(from, progress)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cells 16 pixels wide and 10 tall, on a board 12 cells wide and 6 tall.
+    fn config() -> SnakeConfig {
+        let mut config = crate::default_config();
+        config.grid_width = 12;
+        config.grid_height = 6;
+        config.cell_width = 16;
+        config.cell_height = 10;
+        config
+    }
+
+    fn at(x: f32, y: f32) -> Point2<f32> {
+        Point2 { x, y }
+    }
+
+    #[test]
+    fn a_step_between_neighbours_is_drawn_once() {
+        let config = config();
+        let slid = sliding((3, 2).into(), (4, 2).into(), 0.25, &config);
+        assert_eq!(slid, vec![at(52.0, 20.0)]);
+        let slid = sliding((3, 2).into(), (3, 1).into(), 0.5, &config);
+        assert_eq!(slid, vec![at(48.0, 15.0)]);
+    }
+
+    #[test]
+    fn a_step_off_each_edge_is_drawn_leaving_and_arriving() {
+        let config = config();
+        let progress = 0.25;
+        // Off the right edge and in at the left.
+        let slid = sliding((11, 2).into(), (0, 2).into(), progress, &config);
+        assert_eq!(slid, vec![at(180.0, 20.0), at(-12.0, 20.0)]);
+        // Off the left edge and in at the right.
+        let slid = sliding((0, 2).into(), (11, 2).into(), progress, &config);
+        assert_eq!(slid, vec![at(-4.0, 20.0), at(188.0, 20.0)]);
+        // Off the top and in at the bottom.
+        let slid = sliding((3, 0).into(), (3, 5).into(), progress, &config);
+        assert_eq!(slid, vec![at(48.0, -2.5), at(48.0, 57.5)]);
+        // Off the bottom and in at the top.
+        let slid = sliding((3, 5).into(), (3, 0).into(), progress, &config);
+        assert_eq!(slid, vec![at(48.0, 52.5), at(48.0, -7.5)]);
+    }
+}
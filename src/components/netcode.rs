@@ -0,0 +1,76 @@
+use crate::components::board::Boundary;
+use crate::components::direction::Direction;
+use crate::components::level::Level;
+use crate::components::replay::Replay;
+use crate::components::simulation::Simulation;
+use serde::{Deserialize, Serialize};
+
+/// Everything every machine needs to agree on before a networked game starts,
+/// sent by the host to each player as they join.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Setup {
+    pub seed: u64,
+    pub players: usize,
+    /// How many ticks after being pressed a key takes effect. The longer it is,
+    /// the more time the press has to reach everyone before they need it.
+    pub input_delay: u64,
+    pub grid_width: u16,
+    pub grid_height: u16,
+    pub boundary: Boundary,
+    pub updates_per_second: f32,
+    pub level: Option<Level>,
+}
+
+/// Which `Netcode` a networked game is played with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetcodeKind {
+    /// Everyone waits for everyone's keys before every tick, over TCP, for up
+    /// to four players.
+    #[default]
+    Lockstep,
+    /// Nobody waits, and the game is rolled back whenever a guess at the other
+    /// player's keys turns out wrong, over UDP, for two players.
+    Rollback,
+}
+
+/// A way of keeping the copies of a game on several machines in step. The game
+/// hands it the keys the local player presses and asks it to move the game on
+/// one tick at a time; how it agrees with the others on what everyone pressed
+/// is up to it.
+pub trait Netcode: Send {
+    /// Which player we are, counting from 0 for the host.
+    fn player(&self) -> usize;
+
+    fn setup(&self) -> &Setup;
+
+    /// Whether the next call to `tick` takes the local player's input, so a bot
+    /// playing for them should decide which way to go now.
+    fn needs_input(&self, simulation: &Simulation) -> bool;
+
+    /// Steer the local player's snake towards `dir`.
+    fn steer(&mut self, dir: Direction);
+
+    /// The players we are waiting on before `simulation` can move on.
+    fn waiting_on(&self, simulation: &Simulation) -> Vec<usize>;
+
+    /// Move `simulation` on a tick if it can, recording everyone's inputs in
+    /// `replay` once they are certain. Gives whether it moved on, or an error if
+    /// the game can't go on.
+    fn tick(&mut self, simulation: &mut Simulation, replay: &mut Replay) -> Result<bool, String>;
+
+    /// Send anything that is due between ticks, called as often as the game
+    /// gets the chance.
+    fn flush(&mut self) {}
+
+    /// Whether `simulation` is over for good, and nothing anyone else tells us
+    /// can change how it ended.
+    fn is_settled(&self, simulation: &Simulation) -> bool {
+        simulation.is_over()
+    }
+
+    /// Anything worth telling about how the networking went, once the game is over.
+    fn summary(&self) -> Option<String> {
+        None
+    }
+}
@@ -0,0 +1,184 @@
+use crate::components::board::Board;
+use crate::components::direction::Direction;
+use crate::components::level::Level;
+use crate::components::simulation::Simulation;
+use crate::SnakeConfig;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+/// A single turn the player asked for, along with the tick it was asked for on.
+/// The direction is handed to `Simulation::steer` just before that tick is
+/// stepped, so several inputs on the same tick queue up exactly like key presses
+/// between two updates do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Input {
+    pub tick: u64,
+    /// Which player's snake to steer. Replays from before versus games only
+    /// ever steered the first.
+    #[serde(default)]
+    pub player: usize,
+    pub dir: Direction,
+}
+
+/// A `Replay` is everything needed to play a game back exactly as it happened:
+/// the seed the food was placed with, the config the game ran under and every
+/// input the player gave. We also keep how the game ended so that playing it back
+/// can be checked against the original.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub seed: u64,
+    pub config: SnakeConfig,
+    /// The level the game was played on, if any.
+    #[serde(default)]
+    pub level: Option<Level>,
+    /// How many snakes were playing.
+    #[serde(default = "one_player")]
+    pub players: usize,
+    pub inputs: Vec<Input>,
+    /// How many ticks the game ran for.
+    pub ticks: u64,
+    /// The score at the end of the game.
+    pub score: usize,
+    /// The length of the snake at the end of the game, head included.
+    pub length: usize,
+}
+
+impl Replay {
+    /// Start recording a new game for `players` snakes.
+    pub fn new(seed: u64, config: SnakeConfig, level: Option<Level>, players: usize) -> Self {
+        Replay {
+            seed,
+            config,
+            level,
+            players,
+            inputs: Vec::new(),
+            ticks: 0,
+            score: 0,
+            length: 0,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(serde_json::from_reader(BufReader::new(file))?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        Ok(serde_json::to_writer(BufWriter::new(file), self)?)
+    }
+
+    /// The board the game was played on, or why its level can't be played.
+    pub fn board(&self) -> Result<Board, String> {
+        self.config.board(self.level.as_ref())
+    }
+
+    /// A fresh `Simulation` in the state the recorded game started in.
+    pub fn start(&self) -> Result<Simulation, String> {
+        Ok(Simulation::with_players(
+            self.board()?,
+            self.players,
+            self.seed,
+        ))
+    }
+
+    /// Remember that `player` steered towards `dir` before the next tick of `simulation`.
+    pub fn record(&mut self, simulation: &Simulation, player: usize, dir: Direction) {
+        self.record_at(simulation.ticks(), player, dir);
+    }
+
+    /// Remember that `player` steered towards `dir` just before `tick`. Inputs
+    /// have to be recorded in the order of their ticks.
+    pub fn record_at(&mut self, tick: u64, player: usize, dir: Direction) {
+        self.inputs.push(Input { tick, player, dir });
+    }
+
+    /// Remember how the game ended up.
+    pub fn finish(&mut self, simulation: &Simulation) {
+        self.ticks = simulation.ticks();
+        self.score = simulation.score();
+        self.length = simulation.snake().len();
+    }
+
+    /// Step `simulation` once, first steering it with every recorded input for
+    /// this tick. `cursor` is the index of the next input that hasn't been used yet.
+    pub fn step(&self, simulation: &mut Simulation, cursor: &mut usize) {
+        while let Some(input) = self.inputs.get(*cursor) {
+            if input.tick != simulation.ticks() {
+                break;
+            }
+            simulation.steer_player(input.player, input.dir);
+            *cursor += 1;
+        }
+        simulation.step(None);
+    }
+
+    /// Play the whole game back without drawing anything and check that it ends
+    /// the same way it did when it was recorded.
+    pub fn verify(&self) -> Result<(), String> {
+        let mut simulation = self.start()?;
+        let mut cursor = 0;
+        while simulation.ticks() < self.ticks && !simulation.is_over() {
+            self.step(&mut simulation, &mut cursor);
+        }
+
+        let (score, length) = (simulation.score(), simulation.snake().len());
+        if (score, length) == (self.score, self.length) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Replay ended with score {} and length {}, but {} and {} were recorded",
+                score, length, self.score, self.length
+            ))
+        }
+    }
+}
+
+/// Replays from before versus games were all single player.
+fn one_player() -> usize {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Play a short game, turning now and then, and record it.
+    fn recorded() -> Replay {
+        let mut replay = Replay::new(99, crate::default_config(), None, 1);
+        let mut simulation = replay.start().unwrap();
+        let turns = [
+            Direction::Down,
+            Direction::Right,
+            Direction::Up,
+            Direction::Right,
+        ];
+        for (tick, &dir) in (0..40).step_by(5).zip(turns.iter().cycle()) {
+            while simulation.ticks() < tick {
+                simulation.step(None);
+            }
+            replay.record(&simulation, 0, dir);
+            simulation.step(None);
+        }
+        replay.finish(&simulation);
+        replay
+    }
+
+    #[test]
+    fn a_recorded_game_verifies() {
+        assert_eq!(recorded().verify(), Ok(()));
+    }
+
+    #[test]
+    fn a_replay_that_ends_differently_fails_to_verify() {
+        let mut replay = recorded();
+        replay.score += 1;
+        assert!(replay.verify().is_err());
+
+        let mut replay = recorded();
+        replay.length += 1;
+        assert!(replay.verify().is_err());
+    }
+}
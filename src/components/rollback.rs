@@ -0,0 +1,689 @@
+use crate::components::direction::Direction;
+use crate::components::netcode::{Netcode, Setup};
+use crate::components::replay::Replay;
+use crate::components::simulation::{Simulation, Snapshot};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A bad network to put packets through on their way out, so that rollback can
+/// be tried out with both players on one machine. Every field can be set in the
+/// `[conditions]` table of Config.toml.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Conditions {
+    /// How long every packet takes to leave, in milliseconds.
+    pub latency_ms: u64,
+    /// Up to this many more milliseconds on top, at random, for each packet.
+    pub jitter_ms: u64,
+    /// The fraction of packets that never arrive, from 0 to 1.
+    pub packet_loss: f32,
+}
+
+/// What the two players send each other, one JSON object per UDP packet.
+#[derive(Debug, Serialize, Deserialize)]
+enum Packet {
+    /// From the player joining, over and over until they're welcomed.
+    Hello,
+    /// From the host to the player joining: the game they're about to play.
+    Welcome { setup: Setup },
+    /// The sender's keys for every tick from `first` on that the other player
+    /// hasn't confirmed getting yet, one entry per tick, so a lost packet is
+    /// made up for by the next. `ack` is the first of the other player's ticks
+    /// the sender doesn't have, and `checksum` is of the sender's game at the
+    /// latest tick it is certain of.
+    Inputs {
+        first: u64,
+        inputs: Vec<Vec<Direction>>,
+        ack: u64,
+        checksum: (u64, u64),
+    },
+}
+
+/// A UDP socket to the other player that sends its packets through `Conditions`.
+struct Link {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    conditions: Conditions,
+    rng: StdRng,
+    /// Packets held back by the simulated latency, and when they can go.
+    outbox: Vec<(Instant, Vec<u8>)>,
+}
+
+impl Link {
+    fn new(socket: UdpSocket, peer: SocketAddr, conditions: Conditions) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Link {
+            socket,
+            peer,
+            conditions,
+            rng: StdRng::from_entropy(),
+            outbox: Vec::new(),
+        })
+    }
+
+    fn send(&mut self, packet: &Packet) {
+        if self.rng.gen::<f32>() < self.conditions.packet_loss {
+            return;
+        }
+        let delay =
+            self.conditions.latency_ms + self.rng.gen_range(0, self.conditions.jitter_ms + 1);
+        let bytes = serde_json::to_vec(packet).expect("Packets can always be written as JSON");
+        self.outbox
+            .push((Instant::now() + Duration::from_millis(delay), bytes));
+        self.flush();
+    }
+
+    /// Send every packet whose simulated latency is up.
+    fn flush(&mut self) {
+        let peer = self.peer;
+        let now = Instant::now();
+        let socket = &self.socket;
+        self.outbox.retain(|(due, bytes)| {
+            if *due > now {
+                return true;
+            }
+            if let Err(e) = socket.send_to(bytes, peer) {
+                eprintln!("Couldn't send to {}: {}", peer, e);
+            }
+            false
+        });
+    }
+
+    /// The next packet that has arrived, and who from, if any has. Anything that
+    /// isn't a packet of ours is ignored.
+    fn receive(&mut self) -> Option<(Packet, SocketAddr)> {
+        let mut buffer = [0; 65536];
+        while let Ok((length, from)) = self.socket.recv_from(&mut buffer) {
+            if let Ok(packet) = serde_json::from_slice(&buffer[..length]) {
+                return Some((packet, from));
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Link {
+    /// Packets held back by the simulated latency still go out before we close.
+    fn drop(&mut self) {
+        while !self.outbox.is_empty() {
+            self.flush();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+/// A networked versus game between two players that never waits for the other
+/// player's keys. Our own keys take effect straight away, and we guess that the
+/// other player pressed nothing, so their snake keeps going the way it last
+/// went. When their real keys arrive and they did press something, we put the
+/// game back to a snapshot from just before it and play it forward again with
+/// what they actually pressed.
+///
+/// To keep the guessing in check, we never get more than `max_rollback` ticks
+/// ahead of the last tick we are certain of. And like in lockstep, every
+/// packet carries a checksum of the sender's game at the last tick it is
+/// certain of, which the other player compares against their own.
+pub struct Rollback {
+    player: usize,
+    setup: Setup,
+    link: Link,
+    /// Our keys for each tick the other player may not have yet.
+    local: BTreeMap<u64, Vec<Direction>>,
+    /// The other player's keys, for the ticks we haven't recorded yet.
+    remote: BTreeMap<u64, Vec<Direction>>,
+    /// The first tick we don't have the other player's keys for. Everything
+    /// before it is certain.
+    confirmed: u64,
+    /// The first of our ticks the other player doesn't have yet.
+    acked: u64,
+    /// Snapshots of the game just before each tick it could be rolled back to.
+    snapshots: BTreeMap<u64, Snapshot>,
+    /// The first tick whose inputs haven't been put in the replay yet.
+    recorded: u64,
+    /// The keys the local player pressed since the last tick.
+    pressed: Vec<Direction>,
+    max_rollback: u64,
+    timeout: Duration,
+    last_heard: Instant,
+    /// Whether the other player has stopped answering, after which their
+    /// snake carries on without anyone steering it.
+    dropped: bool,
+    /// Our own checksum of the latest tick we're certain of, by tick, and those
+    /// the other player sent that we haven't got to yet.
+    checksums: BTreeMap<u64, u64>,
+    unchecked: Vec<(u64, u64)>,
+    /// How many times we had to roll back, and how many ticks we played again.
+    rollbacks: u64,
+    replayed: u64,
+}
+
+impl Rollback {
+    /// How many checksums of our own we keep to compare with a slower player.
+    const CHECKSUM_HISTORY: usize = 256;
+    /// How many times we send our last packet once the game is over, to make
+    /// sure the other player gets it.
+    const FINAL_PACKETS: usize = 8;
+
+    /// Host a game for two on `port`, waiting for the other player to join.
+    pub fn host(
+        port: u16,
+        setup: Setup,
+        conditions: Conditions,
+        max_rollback: u64,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        println!("Waiting for the other player on port {}", port);
+        Rollback::host_on(socket, setup, conditions, max_rollback, timeout)
+    }
+
+    /// Host a game for two on `socket`, which is already bound to its port.
+    fn host_on(
+        socket: UdpSocket,
+        setup: Setup,
+        conditions: Conditions,
+        max_rollback: u64,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let mut buffer = [0; 65536];
+        let peer = loop {
+            let (length, from) = socket.recv_from(&mut buffer)?;
+            if let Ok(Packet::Hello) = serde_json::from_slice(&buffer[..length]) {
+                break from;
+            }
+        };
+        println!("Player 2 joined from {}", peer);
+
+        let link = Link::new(socket, peer, conditions)?;
+        let mut session = Rollback::new(0, setup, link, max_rollback, timeout);
+        let welcome = Packet::Welcome {
+            setup: session.setup.clone(),
+        };
+        session.link.send(&welcome);
+        Ok(session)
+    }
+
+    /// Join the game hosted at `address`, such as `192.168.1.20:7777`.
+    pub fn join(
+        address: &str,
+        conditions: Conditions,
+        max_rollback: u64,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let host = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The address doesn't lead anywhere",
+            )
+        })?;
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        let mut link = Link::new(socket, host, conditions)?;
+
+        // Packets can go missing, so we keep saying hello until we hear back.
+        let started = Instant::now();
+        let mut last_hello: Option<Instant> = None;
+        while started.elapsed() < timeout {
+            if last_hello.is_none_or(|hello| hello.elapsed() > Duration::from_millis(250)) {
+                link.send(&Packet::Hello);
+                last_hello = Some(Instant::now());
+            }
+            link.flush();
+            if let Some((Packet::Welcome { setup }, from)) = link.receive() {
+                if from == host {
+                    return Ok(Rollback::new(1, setup, link, max_rollback, timeout));
+                }
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "The host never answered",
+        ))
+    }
+
+    fn new(player: usize, setup: Setup, link: Link, max_rollback: u64, timeout: Duration) -> Self {
+        Rollback {
+            player,
+            setup,
+            link,
+            local: BTreeMap::new(),
+            remote: BTreeMap::new(),
+            confirmed: 0,
+            acked: 0,
+            snapshots: BTreeMap::new(),
+            recorded: 0,
+            pressed: Vec::new(),
+            max_rollback: max_rollback.max(1),
+            timeout,
+            last_heard: Instant::now(),
+            dropped: false,
+            checksums: BTreeMap::new(),
+            unchecked: Vec::new(),
+            rollbacks: 0,
+            replayed: 0,
+        }
+    }
+
+    /// The other player.
+    fn opponent(&self) -> usize {
+        1 - self.player
+    }
+
+    /// The keys `player` pressed for `tick`. For the other player that's what
+    /// they really pressed if we know, and otherwise our guess that they
+    /// pressed nothing.
+    fn input(&self, player: usize, tick: u64) -> Vec<Direction> {
+        let inputs = if player == self.player {
+            &self.local
+        } else {
+            &self.remote
+        };
+        inputs.get(&tick).cloned().unwrap_or_default()
+    }
+
+    /// Play the next tick of `simulation` with the keys we have or guess for it,
+    /// first taking a snapshot to come back to if we guessed wrong.
+    fn simulate(&mut self, simulation: &mut Simulation) {
+        let tick = simulation.ticks();
+        self.snapshots.insert(tick, simulation.snapshot());
+        for player in 0..2 {
+            for dir in self.input(player, tick) {
+                simulation.steer_player(player, dir);
+            }
+        }
+        simulation.step(None);
+    }
+
+    /// Read every packet that has arrived. Gives the earliest tick we already
+    /// played with a wrong guess, if there is one.
+    fn receive(&mut self, simulation: &Simulation) -> Option<u64> {
+        let mut wrong = None;
+        while let Some((packet, from)) = self.link.receive() {
+            if from != self.link.peer {
+                continue;
+            }
+            self.last_heard = Instant::now();
+            match packet {
+                // The welcome got lost, so the other player is still saying hello.
+                Packet::Hello if self.player == 0 => {
+                    let welcome = Packet::Welcome {
+                        setup: self.setup.clone(),
+                    };
+                    self.link.send(&welcome);
+                }
+                // Once we've given up on the other player, their snake goes on
+                // without them even if they come back.
+                Packet::Inputs { .. } if self.dropped => {}
+                Packet::Inputs {
+                    first,
+                    inputs,
+                    ack,
+                    checksum,
+                } => {
+                    self.acked = self.acked.max(ack);
+                    self.unchecked.push(checksum);
+                    for (tick, dirs) in (first..).zip(inputs) {
+                        if tick < self.confirmed || self.remote.contains_key(&tick) {
+                            continue;
+                        }
+                        if tick < simulation.ticks() && !dirs.is_empty() {
+                            wrong = Some(wrong.map_or(tick, |wrong: u64| wrong.min(tick)));
+                        }
+                        self.remote.insert(tick, dirs);
+                    }
+                    while self.remote.contains_key(&self.confirmed) {
+                        self.confirmed += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+        wrong
+    }
+
+    /// Put `simulation` back to just before `tick` and play it forward again to
+    /// where it was, with the keys we know now.
+    fn roll_back(&mut self, simulation: &mut Simulation, tick: u64) {
+        let now = simulation.ticks();
+        let snapshot = match self.snapshots.get(&tick) {
+            Some(snapshot) => snapshot.clone(),
+            None => return,
+        };
+        simulation.restore(&snapshot);
+        self.rollbacks += 1;
+        while simulation.ticks() < now && !simulation.is_over() {
+            self.simulate(simulation);
+            self.replayed += 1;
+        }
+    }
+
+    /// Put the inputs of every tick we're now certain of in `replay`, and let go
+    /// of whatever we won't need again. Gives the latest tick we're certain of.
+    fn settle(&mut self, simulation: &Simulation, replay: &mut Replay) -> u64 {
+        let certain = if self.dropped {
+            simulation.ticks()
+        } else {
+            self.confirmed.min(simulation.ticks())
+        };
+        for tick in self.recorded..certain {
+            for player in 0..2 {
+                for dir in self.input(player, tick) {
+                    replay.record_at(tick, player, dir);
+                }
+            }
+        }
+        self.recorded = self.recorded.max(certain);
+
+        let checksum = match self.snapshots.get(&certain) {
+            Some(snapshot) if certain < simulation.ticks() => snapshot.checksum(),
+            _ => simulation.checksum(),
+        };
+        self.checksums.insert(certain, checksum);
+        while self.checksums.len() > Rollback::CHECKSUM_HISTORY {
+            let oldest = *self.checksums.keys().next().expect("There are checksums");
+            self.checksums.remove(&oldest);
+        }
+
+        self.snapshots = self.snapshots.split_off(&certain);
+        self.remote = self.remote.split_off(&self.recorded);
+        self.local = self.local.split_off(&self.recorded.min(self.acked));
+        certain
+    }
+
+    /// Compare the checksums the other player sent with our own for the same tick.
+    fn check(&mut self) -> Result<(), String> {
+        let checksums = &self.checksums;
+        let mut desync = None;
+        self.unchecked
+            .retain(|&(tick, checksum)| match checksums.get(&tick) {
+                Some(&ours) => {
+                    if ours != checksum {
+                        desync = Some(tick);
+                    }
+                    false
+                }
+                None => checksums.keys().next().is_none_or(|&oldest| tick > oldest),
+            });
+        match desync {
+            Some(tick) => Err(format!(
+                "Out of sync with Player {} at tick {}",
+                self.opponent() + 1,
+                tick
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Send the other player our keys for every tick they don't have yet.
+    fn send_inputs(&mut self, simulation: &Simulation, certain: u64) {
+        let inputs = (self.acked..simulation.ticks())
+            .map(|tick| self.input(self.player, tick))
+            .collect();
+        let packet = Packet::Inputs {
+            first: self.acked,
+            inputs,
+            ack: self.confirmed,
+            checksum: (certain, self.checksums[&certain]),
+        };
+        self.link.send(&packet);
+    }
+}
+
+impl Netcode for Rollback {
+    fn player(&self) -> usize {
+        self.player
+    }
+
+    fn setup(&self) -> &Setup {
+        &self.setup
+    }
+
+    /// Whether the next tick can be played without getting too far ahead of
+    /// what we're certain of.
+    fn needs_input(&self, simulation: &Simulation) -> bool {
+        !simulation.is_over()
+            && (self.dropped || simulation.ticks() < self.confirmed + self.max_rollback)
+    }
+
+    /// Steer the local player's snake towards `dir`, on the very next tick.
+    fn steer(&mut self, dir: Direction) {
+        self.pressed.push(dir);
+    }
+
+    fn waiting_on(&self, simulation: &Simulation) -> Vec<usize> {
+        if self.is_settled(simulation) || self.needs_input(simulation) {
+            Vec::new()
+        } else {
+            vec![self.opponent()]
+        }
+    }
+
+    fn tick(&mut self, simulation: &mut Simulation, replay: &mut Replay) -> Result<bool, String> {
+        let was_settled = self.is_settled(simulation);
+        // Only a tick the caller was told takes input gets played, so whoever is
+        // steering has had the chance to, even if rolling back below changes
+        // a game that looked over.
+        let ready = self.needs_input(simulation);
+        if let Some(tick) = self.receive(simulation) {
+            self.roll_back(simulation, tick);
+        }
+        if !self.dropped && !was_settled && self.last_heard.elapsed() > self.timeout {
+            eprintln!("Player {} stopped answering", self.opponent() + 1);
+            self.dropped = true;
+        }
+
+        let stepped = ready && self.needs_input(simulation);
+        if stepped {
+            self.local
+                .insert(simulation.ticks(), std::mem::take(&mut self.pressed));
+            self.simulate(simulation);
+        }
+
+        let certain = self.settle(simulation, replay);
+        self.check()?;
+        // Once the game is over for good this is the last we'll say, so we say
+        // it a few times over in case some of it goes missing.
+        let packets = if !was_settled && self.is_settled(simulation) {
+            Rollback::FINAL_PACKETS
+        } else {
+            1
+        };
+        if !self.dropped {
+            for _ in 0..packets {
+                self.send_inputs(simulation, certain);
+            }
+        }
+        self.link.flush();
+        Ok(stepped)
+    }
+
+    /// Packets held back by the simulated latency go out when they're due, not
+    /// only when the next tick comes around.
+    fn flush(&mut self) {
+        self.link.flush();
+    }
+
+    /// The game can only be over for good once we're certain of every tick
+    /// that led up to the end, since the other player's real keys could still
+    /// change how it went.
+    fn is_settled(&self, simulation: &Simulation) -> bool {
+        simulation.is_over() && (self.dropped || self.confirmed >= simulation.ticks())
+    }
+
+    fn summary(&self) -> Option<String> {
+        Some(format!(
+            "Rolled back {} times, playing {} ticks over again",
+            self.rollbacks, self.replayed
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::board::{Board, Boundary};
+
+    fn setup() -> Setup {
+        Setup {
+            seed: 7,
+            players: 2,
+            input_delay: 0,
+            grid_width: 20,
+            grid_height: 16,
+            boundary: Boundary::Walls,
+            updates_per_second: 8.0,
+            level: None,
+        }
+    }
+
+    fn simulation(setup: &Setup) -> Simulation {
+        let board = Board::new(setup.grid_width, setup.grid_height, setup.boundary);
+        Simulation::with_players(board, setup.players, setup.seed)
+    }
+
+    fn replay(setup: &Setup) -> Replay {
+        Replay::new(setup.seed, crate::default_config(), None, setup.players)
+    }
+
+    /// The turns each player makes, by tick. Both snakes end up in a wall
+    /// soon after, so the game doesn't go on for long.
+    fn turn(player: usize, tick: u64) -> Option<Direction> {
+        match (player, tick) {
+            (0, 2) | (0, 8) => Some(Direction::Up),
+            (0, 5) => Some(Direction::Right),
+            (1, 3) | (1, 9) => Some(Direction::Down),
+            (1, 6) => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    /// The game as lockstep would play it, with everyone's real keys known
+    /// before every tick, up to `ticks`.
+    fn played_in_step(setup: &Setup, ticks: u64) -> Simulation {
+        let mut simulation = simulation(setup);
+        while simulation.ticks() < ticks && !simulation.is_over() {
+            for player in 0..2 {
+                if let Some(dir) = turn(player, simulation.ticks()) {
+                    simulation.steer_player(player, dir);
+                }
+            }
+            simulation.step(None);
+        }
+        simulation
+    }
+
+    #[test]
+    fn a_restored_snapshot_plays_out_the_same_again() {
+        let setup = setup();
+        let mut simulation = played_in_step(&setup, 4);
+        let snapshot = simulation.snapshot();
+        assert_eq!(snapshot.checksum(), simulation.checksum());
+
+        let play_on = |simulation: &mut Simulation| {
+            for _ in 0..6 {
+                for player in 0..2 {
+                    if let Some(dir) = turn(player, simulation.ticks()) {
+                        simulation.steer_player(player, dir);
+                    }
+                }
+                simulation.step(None);
+            }
+            simulation.checksum()
+        };
+        let first = play_on(&mut simulation);
+        simulation.restore(&snapshot);
+        assert_eq!(simulation.checksum(), snapshot.checksum());
+        assert_eq!(play_on(&mut simulation), first);
+    }
+
+    #[test]
+    fn keys_that_arrive_late_are_played_as_if_they_were_on_time() {
+        let setup = setup();
+        let peer = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let ours = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let address = ours.local_addr().unwrap();
+        let link = Link::new(ours, peer.local_addr().unwrap(), Conditions::default()).unwrap();
+        let mut session = Rollback::new(0, setup.clone(), link, 20, Duration::from_secs(10));
+        let mut simulation = simulation(&setup);
+        let mut replay = replay(&setup);
+
+        // We play ahead guessing that the other player pressed nothing...
+        for tick in 0..8 {
+            if let Some(dir) = turn(0, tick) {
+                session.steer(dir);
+            }
+            assert!(session.tick(&mut simulation, &mut replay).unwrap());
+        }
+        assert_ne!(simulation.checksum(), played_in_step(&setup, 8).checksum());
+
+        // ...until their keys for those ticks turn up, with a turn on tick 3.
+        let packet = Packet::Inputs {
+            first: 0,
+            inputs: (0..8)
+                .map(|tick| turn(1, tick).into_iter().collect())
+                .collect(),
+            ack: 8,
+            checksum: (0, self::simulation(&setup).checksum()),
+        };
+        peer.send_to(&serde_json::to_vec(&packet).unwrap(), address)
+            .unwrap();
+        thread::sleep(Duration::from_millis(50));
+        session.steer(turn(0, 8).unwrap());
+        assert!(session.tick(&mut simulation, &mut replay).unwrap());
+
+        assert_eq!(session.rollbacks, 1);
+        assert_eq!(session.confirmed, 8);
+        assert_eq!(simulation.checksum(), played_in_step(&setup, 9).checksum());
+    }
+
+    /// Play the game of `session` until it is settled, giving the checksum and
+    /// tick it ended on.
+    fn play(mut session: Rollback) -> Result<(u64, u64), String> {
+        let setup = session.setup().clone();
+        let mut simulation = simulation(&setup);
+        let mut replay = replay(&setup);
+        let player = session.player();
+        let started = Instant::now();
+        while !session.is_settled(&simulation) {
+            if started.elapsed() > Duration::from_secs(30) {
+                return Err(format!("Player {} never finished", player + 1));
+            }
+            if session.needs_input(&simulation) {
+                if let Some(dir) = turn(player, simulation.ticks()) {
+                    session.steer(dir);
+                }
+            }
+            session.tick(&mut simulation, &mut replay)?;
+            thread::sleep(Duration::from_millis(2));
+        }
+        Ok((simulation.checksum(), simulation.ticks()))
+    }
+
+    #[test]
+    fn both_players_agree_over_a_bad_network() {
+        let conditions = Conditions {
+            latency_ms: 20,
+            jitter_ms: 10,
+            packet_loss: 0.2,
+        };
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        let timeout = Duration::from_secs(10);
+
+        let joining =
+            thread::spawn(move || play(Rollback::join(&address, conditions, 8, timeout).unwrap()));
+        let host = Rollback::host_on(socket, setup(), conditions, 8, timeout).unwrap();
+        let hosted = play(host).unwrap();
+
+        assert_eq!(joining.join().unwrap().unwrap(), hosted);
+        let expected = played_in_step(&setup(), u64::MAX);
+        assert!(expected.is_over());
+        assert_eq!(hosted, (expected.checksum(), expected.ticks()));
+    }
+}
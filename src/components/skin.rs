@@ -0,0 +1,291 @@
+use crate::components::direction::Direction;
+use crate::components::draw::{cell_corner, Batch};
+use crate::components::grid_position::GridPosition;
+use crate::{SnakeConfig, SNAKE_CONFIG};
+use ggez::graphics::{DrawParam, FilterMode, Image, Rect};
+use ggez::mint::{Point2, Vector2};
+use ggez::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// A `Skin` draws the snakes and the food with sprites instead of flat colored
+/// squares. It lives in a folder of its own, such as `assets/skins/classic`,
+/// with a `skin.toml` manifest saying which image holds the sprites and where
+/// in that image each one is.
+pub struct Skin {
+    atlas: Image,
+    manifest: Manifest,
+}
+
+/// The `skin.toml` of a skin. Every sprite is given as the `[column, row]` of
+/// the tile it is in, counting from the top left of the atlas.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// The atlas image, relative to the skin's folder.
+    pub atlas: String,
+    pub tile_width: u16,
+    pub tile_height: u16,
+    /// Heads face the way the snake is going.
+    pub head_up: [u16; 2],
+    pub head_down: [u16; 2],
+    pub head_left: [u16; 2],
+    pub head_right: [u16; 2],
+    /// Tails are named for the side the rest of the body is on.
+    pub tail_up: [u16; 2],
+    pub tail_down: [u16; 2],
+    pub tail_left: [u16; 2],
+    pub tail_right: [u16; 2],
+    pub body_horizontal: [u16; 2],
+    pub body_vertical: [u16; 2],
+    /// Corners are named for the two sides of the cell they join.
+    pub corner_up_left: [u16; 2],
+    pub corner_up_right: [u16; 2],
+    pub corner_down_left: [u16; 2],
+    pub corner_down_right: [u16; 2],
+    pub food: [u16; 2],
+}
+
+impl Manifest {
+    fn head(&self, dir: Direction) -> [u16; 2] {
+        match dir {
+            Direction::Up => self.head_up,
+            Direction::Down => self.head_down,
+            Direction::Left => self.head_left,
+            Direction::Right => self.head_right,
+        }
+    }
+
+    fn tail(&self, dir: Direction) -> [u16; 2] {
+        match dir {
+            Direction::Up => self.tail_up,
+            Direction::Down => self.tail_down,
+            Direction::Left => self.tail_left,
+            Direction::Right => self.tail_right,
+        }
+    }
+
+    /// The piece of body that joins the sides `a` and `b` of its cell.
+    fn body(&self, a: Direction, b: Direction) -> [u16; 2] {
+        use Direction::*;
+        match (a, b) {
+            (Up, Left) | (Left, Up) => self.corner_up_left,
+            (Up, Right) | (Right, Up) => self.corner_up_right,
+            (Down, Left) | (Left, Down) => self.corner_down_left,
+            (Down, Right) | (Right, Down) => self.corner_down_right,
+            (Left, _) | (Right, _) => self.body_horizontal,
+            (Up, _) | (Down, _) => self.body_vertical,
+        }
+    }
+}
+
+impl Skin {
+    /// Read the skin in the folder `dir`.
+    pub fn load(ctx: &mut Context, dir: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(dir.join("skin.toml")).map_err(|e| e.to_string())?;
+        let manifest: Manifest = toml::from_str(&text).map_err(|e| e.to_string())?;
+        if manifest.tile_width == 0 || manifest.tile_height == 0 {
+            return Err(String::from("tile_width and tile_height can't be 0"));
+        }
+        // We decode the atlas ourselves rather than going through ggez, which
+        // only loads images from the folders it has mounted.
+        let path = dir.join(&manifest.atlas);
+        let pixels = image::open(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?
+            .to_rgba();
+        let (width, height) = pixels.dimensions();
+        if width > u16::MAX as u32 || height > u16::MAX as u32 {
+            return Err(format!("{} is too big", path.display()));
+        }
+        let mut atlas = Image::from_rgba8(ctx, width as u16, height as u16, &pixels)
+            .map_err(|e| e.to_string())?;
+        // Sprites are small, so we keep their pixels crisp when they're stretched.
+        atlas.set_filter(FilterMode::Nearest);
+        Ok(Skin { atlas, manifest })
+    }
+
+    /// Draw a snake heading `dir` covering `cells`, from its head to its tail,
+    /// tinted `tint`.
+    pub fn draw_snake(
+        &self,
+        cells: &[GridPosition],
+        dir: Direction,
+        tint: [f32; 4],
+        batch: &mut Batch,
+    ) {
+        for (i, &pos) in cells.iter().enumerate() {
+            let ahead = match i.checked_sub(1) {
+                Some(i) => Ahead::Cell(cells[i]),
+                None => Ahead::Heading(dir),
+            };
+            let behind = cells.get(i + 1).copied();
+            self.draw_piece(
+                ahead,
+                pos,
+                behind,
+                cell_corner(pos, batch.config()),
+                tint,
+                batch,
+            );
+        }
+    }
+
+    /// Draw the piece of a snake on the cell `pos` at `at`, in pixels. The
+    /// piece joins what is `ahead` of it, towards the head, and the cell
+    /// `behind` it, towards the tail. Without anything behind it's the tail.
+    pub fn draw_piece(
+        &self,
+        ahead: Ahead,
+        pos: GridPosition,
+        behind: Option<GridPosition>,
+        at: Point2<f32>,
+        tint: [f32; 4],
+        batch: &mut Batch,
+    ) {
+        let tile = match (ahead, behind) {
+            (Ahead::Heading(dir), _) => self.manifest.head(dir),
+            (Ahead::Cell(ahead), None) => self.manifest.tail(towards(pos, ahead)),
+            (Ahead::Cell(ahead), Some(behind)) => self
+                .manifest
+                .body(towards(pos, ahead), towards(pos, behind)),
+        };
+        self.draw_tile(at, tile, tint, batch);
+    }
+
+    pub fn draw_food(&self, pos: GridPosition, batch: &mut Batch) {
+        self.draw_tile(
+            cell_corner(pos, batch.config()),
+            self.manifest.food,
+            [1.0, 1.0, 1.0, 1.0],
+            batch,
+        );
+    }
+
+    /// Stretch the sprite in `tile` over a cell at `at`, in pixels.
+    fn draw_tile(&self, at: Point2<f32>, tile: [u16; 2], tint: [f32; 4], batch: &mut Batch) {
+        let (width, height) = (self.atlas.width() as f32, self.atlas.height() as f32);
+        let (tile_width, tile_height) = (
+            self.manifest.tile_width as f32,
+            self.manifest.tile_height as f32,
+        );
+        // The source rectangle is given as a fraction of the whole atlas.
+        let src = Rect::new(
+            tile[0] as f32 * tile_width / width,
+            tile[1] as f32 * tile_height / height,
+            tile_width / width,
+            tile_height / height,
+        );
+        let param = DrawParam::new()
+            .src(src)
+            .dest(at)
+            .scale(Vector2 {
+                x: batch.config().cell_width as f32 / tile_width,
+                y: batch.config().cell_height as f32 / tile_height,
+            })
+            .color(tint.into());
+        batch.draw_sprite(&self.atlas, param);
+    }
+}
+
+/// What is ahead of a piece of snake, towards its head.
+#[derive(Clone, Copy, Debug)]
+pub enum Ahead {
+    /// Nothing, since the piece is the head, which faces the way the snake is
+    /// heading.
+    Heading(Direction),
+    /// The cell of the next piece.
+    Cell(GridPosition),
+}
+
+/// The skin from the config, if it has one. Images can only be loaded once
+/// there is a window, so we load it the first time the game is drawn. If it
+/// can't be loaded we say why and go back to flat colors.
+#[derive(Default)]
+pub struct LoadedSkin {
+    skin: Option<Option<Skin>>,
+}
+
+impl LoadedSkin {
+    pub fn get(&mut self, ctx: &mut Context) -> Option<&Skin> {
+        self.skin
+            .get_or_insert_with(|| {
+                let dir = SNAKE_CONFIG.get().skin.clone()?;
+                Skin::load(ctx, Path::new(&dir))
+                    .map_err(|e| eprintln!("Couldn't load skin {}, using colors: {}", dir, e))
+                    .ok()
+            })
+            .as_ref()
+    }
+}
+
+/// The tint for the snake of `player`. The first player's snake looks just
+/// like the skin, and the others take on their head color in `config` so they
+/// can be told apart.
+pub fn tint(player: usize, config: &SnakeConfig) -> [f32; 4] {
+    match player {
+        0 => [1.0, 1.0, 1.0, 1.0],
+        _ => config.snake_colors(player).0,
+    }
+}
+
+/// Which way `to` is from `from`, the cell next to it. On a board that wraps,
+/// cells on opposite edges are next to each other too, and then `to` looks to
+/// be the whole board away in the other direction.
+fn towards(from: GridPosition, to: GridPosition) -> Direction {
+    let dx = to.x as i32 - from.x as i32;
+    let dy = to.y as i32 - from.y as i32;
+    match (dx, dy) {
+        (1, _) => Direction::Right,
+        (-1, _) => Direction::Left,
+        (_, 1) => Direction::Down,
+        (_, -1) => Direction::Up,
+        (dx, _) if dx > 1 => Direction::Left,
+        (dx, _) if dx < -1 => Direction::Right,
+        (_, dy) if dy > 1 => Direction::Up,
+        _ => Direction::Down,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn towards_finds_the_way_to_a_neighbour() {
+        let from = GridPosition::new(3, 2);
+        assert_eq!(towards(from, (4, 2).into()), Direction::Right);
+        assert_eq!(towards(from, (2, 2).into()), Direction::Left);
+        assert_eq!(towards(from, (3, 1).into()), Direction::Up);
+        assert_eq!(towards(from, (3, 3).into()), Direction::Down);
+    }
+
+    #[test]
+    fn towards_goes_the_short_way_across_the_edge() {
+        // On a board 12 cells wide and 6 tall that wraps.
+        assert_eq!(towards((11, 2).into(), (0, 2).into()), Direction::Right);
+        assert_eq!(towards((0, 2).into(), (11, 2).into()), Direction::Left);
+        assert_eq!(towards((3, 0).into(), (3, 5).into()), Direction::Up);
+        assert_eq!(towards((3, 5).into(), (3, 0).into()), Direction::Down);
+    }
+
+    #[test]
+    fn body_pieces_join_the_sides_they_are_named_for() {
+        let manifest: Manifest =
+            toml::from_str(include_str!("../../assets/skins/classic/skin.toml")).unwrap();
+        use Direction::*;
+        for (a, b) in [(Up, Left), (Left, Up)] {
+            assert_eq!(manifest.body(a, b), manifest.corner_up_left);
+        }
+        for (a, b) in [(Up, Right), (Right, Up)] {
+            assert_eq!(manifest.body(a, b), manifest.corner_up_right);
+        }
+        for (a, b) in [(Down, Left), (Left, Down)] {
+            assert_eq!(manifest.body(a, b), manifest.corner_down_left);
+        }
+        for (a, b) in [(Down, Right), (Right, Down)] {
+            assert_eq!(manifest.body(a, b), manifest.corner_down_right);
+        }
+        assert_eq!(manifest.body(Left, Right), manifest.body_horizontal);
+        assert_eq!(manifest.body(Up, Down), manifest.body_vertical);
+    }
+}
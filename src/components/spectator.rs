@@ -0,0 +1,513 @@
+use crate::components::board::{Board, Boundary};
+use crate::components::direction::Direction;
+use crate::components::draw::{
+    draw_border, draw_food, draw_snake, draw_text, draw_walls, follow, show_screen, Batch,
+    CachedMesh,
+};
+use crate::components::grid_position::GridPosition;
+use crate::components::simulation::Simulation;
+use crate::components::skin::{LoadedSkin, Skin};
+use crate::SNAKE_CONFIG;
+use ggez::event::{self, KeyCode, KeyMods};
+use ggez::{graphics, Context, GameResult};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use tungstenite::Message;
+
+/// Everything a spectator needs to draw a game: the board, every snake and the
+/// food. Viewers are sent one as they join, and then kept up to date with a
+/// `Delta` for every tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct View {
+    pub width: u16,
+    pub height: u16,
+    pub boundary: Boundary,
+    pub walls: Vec<GridPosition>,
+    pub tick: u64,
+    pub snakes: Vec<SnakeView>,
+    pub food: GridPosition,
+    /// How the game ended, once it has.
+    pub ended: Option<Ending>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnakeView {
+    /// Every cell of the snake, from the head to the tail.
+    pub cells: Vec<GridPosition>,
+    /// The way the snake is heading, which its head faces.
+    pub dir: Direction,
+    pub alive: bool,
+    pub score: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Ending {
+    /// Whether the snakes filled the board.
+    pub won: bool,
+    /// The last snake left in a versus game, if there was one.
+    pub winner: Option<usize>,
+}
+
+/// What changed in a game over a tick.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Delta {
+    pub tick: u64,
+    /// The snakes that moved.
+    pub moves: Vec<Move>,
+    /// Where the food spawned, if it was eaten.
+    pub food: Option<GridPosition>,
+    /// The players whose snakes died.
+    pub deaths: Vec<usize>,
+    /// The new score of every player whose score changed.
+    pub scores: Vec<(usize, usize)>,
+    pub ended: Option<Ending>,
+}
+
+/// A snake moving its head to `head`, and its tail along behind it so that it
+/// is `length` cells long, or just turning to head `dir` if its head is already
+/// there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub player: usize,
+    pub head: GridPosition,
+    pub length: usize,
+    pub dir: Direction,
+}
+
+/// What the spectator server sends its viewers, one JSON object per WebSocket
+/// text message.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Broadcast {
+    /// The whole game, sent to each viewer as they join and whenever the game
+    /// changes in a way a delta can't describe, such as a new game starting.
+    Snapshot(View),
+    Delta(Delta),
+}
+
+impl View {
+    /// How `simulation` looks right now.
+    pub fn of(simulation: &Simulation) -> Self {
+        let board = simulation.board();
+        let mut walls: Vec<GridPosition> = board.walls.iter().copied().collect();
+        walls.sort_by_key(|pos| (pos.y, pos.x));
+        View {
+            width: board.width,
+            height: board.height,
+            boundary: board.boundary,
+            walls,
+            tick: simulation.ticks(),
+            snakes: (0..simulation.players())
+                .map(|player| {
+                    let snake = &simulation.snakes()[player];
+                    SnakeView {
+                        cells: snake.positions().collect(),
+                        dir: snake.dir,
+                        alive: snake.is_alive(),
+                        score: simulation.score_of(player),
+                    }
+                })
+                .collect(),
+            food: simulation.food().pos,
+            ended: if simulation.is_over() {
+                Some(Ending {
+                    won: simulation.has_won(),
+                    winner: simulation.winner(),
+                })
+            } else {
+                None
+            },
+        }
+    }
+
+    /// What changed between this view and `next`, as far as a delta can tell.
+    /// Only things that happen over a tick are covered, so check with `apply`
+    /// that the delta really gets from one to the other.
+    pub fn delta(&self, next: &View) -> Delta {
+        let mut delta = Delta {
+            tick: next.tick,
+            moves: Vec::new(),
+            food: if next.food != self.food {
+                Some(next.food)
+            } else {
+                None
+            },
+            deaths: Vec::new(),
+            scores: Vec::new(),
+            ended: next.ended,
+        };
+        for (player, (before, after)) in self.snakes.iter().zip(&next.snakes).enumerate() {
+            if before.cells != after.cells || before.dir != after.dir {
+                delta.moves.push(Move {
+                    player,
+                    head: after.cells[0],
+                    length: after.cells.len(),
+                    dir: after.dir,
+                });
+            }
+            if before.alive && !after.alive {
+                delta.deaths.push(player);
+            }
+            if before.score != after.score {
+                delta.scores.push((player, after.score));
+            }
+        }
+        delta
+    }
+
+    /// Bring this view up to date with `delta`.
+    pub fn apply(&mut self, delta: &Delta) {
+        self.tick = delta.tick;
+        for step in &delta.moves {
+            if let Some(snake) = self.snakes.get_mut(step.player) {
+                if snake.cells.first() != Some(&step.head) {
+                    snake.cells.insert(0, step.head);
+                }
+                snake.cells.truncate(step.length);
+                snake.dir = step.dir;
+            }
+        }
+        if let Some(food) = delta.food {
+            self.food = food;
+        }
+        for &player in &delta.deaths {
+            if let Some(snake) = self.snakes.get_mut(player) {
+                snake.alive = false;
+            }
+        }
+        for &(player, score) in &delta.scores {
+            if let Some(snake) = self.snakes.get_mut(player) {
+                snake.score = score;
+            }
+        }
+        self.ended = delta.ended;
+    }
+
+    /// The view draws the game into `batch` the same way the game draws itself.
+    pub fn draw(&self, skin: Option<&Skin>, batch: &mut Batch) {
+        let mut board = Board::new(self.width, self.height, self.boundary);
+        board.walls = self.walls.iter().copied().collect();
+        draw_walls(&board, batch);
+        for (player, snake) in self.snakes.iter().enumerate() {
+            draw_snake(&snake.cells, snake.dir, player, skin, batch);
+        }
+        if !self.ended.is_some_and(|ending| ending.won) {
+            draw_food(self.food, skin, batch);
+        }
+    }
+}
+
+/// A server that streams the game being played to anyone who wants to watch
+/// it over a WebSocket. Every viewer gets a writer thread of their own, so a
+/// slow viewer never holds up the game or the other viewers.
+pub struct Spectators {
+    /// The channel to each viewer's writer thread.
+    viewers: Vec<Sender<String>>,
+    /// Viewers who have just connected and still need a snapshot.
+    joined: Receiver<Sender<String>>,
+    /// The game as every viewer last saw it.
+    view: Option<View>,
+}
+
+impl Spectators {
+    /// Start listening for viewers on `port`.
+    pub fn serve(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        println!("Spectators can watch with --watch <address>:{}", port);
+        let (sender, joined) = channel();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || send_to_viewer(stream, sender));
+            }
+        });
+        Ok(Spectators {
+            viewers: Vec::new(),
+            joined,
+            view: None,
+        })
+    }
+
+    /// Tell every viewer what changed in `simulation` since they last heard,
+    /// and show anyone who just joined the whole game.
+    pub fn update(&mut self, simulation: &Simulation) {
+        let view = View::of(simulation);
+        let broadcast = match &self.view {
+            Some(last) if *last == view => None,
+            Some(last) => {
+                let delta = last.delta(&view);
+                let mut check = last.clone();
+                check.apply(&delta);
+                if check == view {
+                    Some(Broadcast::Delta(delta))
+                } else {
+                    Some(Broadcast::Snapshot(view.clone()))
+                }
+            }
+            None => Some(Broadcast::Snapshot(view.clone())),
+        };
+        if let Some(broadcast) = broadcast {
+            let text =
+                serde_json::to_string(&broadcast).expect("The game can always be written as JSON");
+            self.viewers
+                .retain(|viewer| viewer.send(text.clone()).is_ok());
+        }
+
+        let snapshot = serde_json::to_string(&Broadcast::Snapshot(view.clone()))
+            .expect("The game can always be written as JSON");
+        while let Ok(viewer) = self.joined.try_recv() {
+            if viewer.send(snapshot.clone()).is_ok() {
+                self.viewers.push(viewer);
+            }
+        }
+        self.view = Some(view);
+    }
+
+    /// Whether anyone is watching, as of the last `update`.
+    pub fn is_watched(&self) -> bool {
+        !self.viewers.is_empty()
+    }
+}
+
+/// Shake hands with a viewer who just connected, then send them everything
+/// the game hands us for them until they go away.
+fn send_to_viewer(stream: TcpStream, joined: Sender<Sender<String>>) {
+    let address = stream.peer_addr().map(|address| address.to_string());
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("A spectator couldn't connect: {}", e);
+            return;
+        }
+    };
+    let (sender, messages) = channel();
+    if joined.send(sender).is_err() {
+        return;
+    }
+    println!("Spectator joined from {}", address.unwrap_or_default());
+    for text in messages {
+        if socket.send(Message::Text(text)).is_err() {
+            break;
+        }
+    }
+}
+
+/// A spectator watching a game served by `Spectators` somewhere else. Run as
+/// the game's event handler, it draws the game as it is streamed to us.
+pub struct Viewer {
+    view: View,
+    /// How many updates we've had, which is all it takes to know whether the
+    /// board needs drawing again.
+    version: u64,
+    mesh: CachedMesh,
+    skin: LoadedSkin,
+    updates: Receiver<Broadcast>,
+    /// Whether the server went away.
+    disconnected: bool,
+}
+
+impl Viewer {
+    /// Connect to the spectator server at `address`, such as `192.168.1.20:7878`,
+    /// and wait for the first snapshot of the game.
+    pub fn connect(address: &str) -> Result<Self, String> {
+        let stream = TcpStream::connect(address).map_err(|e| e.to_string())?;
+        let (mut socket, _) =
+            tungstenite::client(format!("ws://{}/", address), stream).map_err(|e| e.to_string())?;
+        let view = match read_broadcast(&mut socket)? {
+            Broadcast::Snapshot(view) => view,
+            Broadcast::Delta(_) => {
+                return Err(String::from("The server didn't start with a snapshot"))
+            }
+        };
+
+        let (sender, updates) = channel();
+        thread::spawn(move || {
+            while let Ok(broadcast) = read_broadcast(&mut socket) {
+                if sender.send(broadcast).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Viewer {
+            view,
+            version: 0,
+            mesh: CachedMesh::default(),
+            skin: LoadedSkin::default(),
+            updates,
+            disconnected: false,
+        })
+    }
+
+    /// The game as we last heard of it.
+    pub fn view(&self) -> &View {
+        &self.view
+    }
+}
+
+/// Read the next broadcast from the spectator server, skipping over anything
+/// that isn't one.
+fn read_broadcast(socket: &mut tungstenite::WebSocket<TcpStream>) -> Result<Broadcast, String> {
+    loop {
+        match socket.read().map_err(|e| e.to_string())? {
+            Message::Text(text) => {
+                return serde_json::from_str(&text).map_err(|e| e.to_string());
+            }
+            Message::Close(_) => return Err(String::from("The server closed the connection")),
+            _ => {}
+        }
+    }
+}
+
+impl event::EventHandler for Viewer {
+    /// Catch up on everything the server sent since the last frame.
+    fn update(&mut self, _ctx: &mut Context) -> GameResult {
+        loop {
+            match self.updates.try_recv() {
+                Ok(Broadcast::Snapshot(view)) => {
+                    self.view = view;
+                    self.version += 1;
+                }
+                Ok(Broadcast::Delta(delta)) => {
+                    self.view.apply(&delta);
+                    self.version += 1;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.disconnected = true;
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let config = SNAKE_CONFIG.get();
+        graphics::clear(ctx, config.background.into());
+        let head = self
+            .view
+            .snakes
+            .first()
+            .and_then(|snake| snake.cells.first());
+        follow(
+            head.copied().unwrap_or(GridPosition::new(0, 0)),
+            &config,
+            ctx,
+        )?;
+        if self.view.boundary == Boundary::Walls {
+            draw_border(config.wall, &config, ctx)?;
+        }
+        let view = &self.view;
+        let skin = self.skin.get(ctx);
+        self.mesh
+            .draw(ctx, &config, self.version, |batch| view.draw(skin, batch))?;
+        show_screen(&config, ctx)?;
+
+        let scores: Vec<String> = self
+            .view
+            .snakes
+            .iter()
+            .enumerate()
+            .map(|(player, snake)| format!("P{} {}", player + 1, snake.score))
+            .collect();
+        let status = format!("Watching, tick {}, {}", self.view.tick, scores.join(" "));
+        draw_text(0, status, ctx)?;
+
+        let title = if self.disconnected {
+            Some(String::from("The game is no longer being served."))
+        } else {
+            self.view.ended.map(|ending| match ending.winner {
+                _ if ending.won => String::from("Board Full!"),
+                Some(winner) => format!("Player {} Wins!", winner + 1),
+                None if self.view.snakes.len() > 1 => String::from("It's a Draw!"),
+                None => String::from("Game Over!"),
+            })
+        };
+        if let Some(title) = title {
+            draw_text(2, title, ctx)?;
+        }
+        graphics::present(ctx)?;
+        ggez::timer::yield_now();
+        Ok(())
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: KeyCode,
+        _keymod: KeyMods,
+        _repeat: bool,
+    ) {
+        if keycode == KeyCode::Escape {
+            event::quit(ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::controller::{Controller, Pathfinder};
+
+    #[test]
+    fn deltas_keep_a_viewer_in_step_with_the_game() {
+        let board = Board::new(12, 10, Boundary::Walls);
+        let mut simulation = Simulation::with_players(board, 2, 3);
+        let mut viewer = View::of(&simulation);
+        let mut food_moved = false;
+        while !simulation.is_over() && simulation.ticks() < 500 {
+            for player in 0..simulation.players() {
+                if simulation.snakes()[player].is_alive() {
+                    let dir = Pathfinder.next_dir(&simulation, player);
+                    simulation.steer_player(player, dir);
+                }
+            }
+            simulation.step(None);
+
+            let next = View::of(&simulation);
+            let delta = viewer.delta(&next);
+            food_moved |= delta.food.is_some();
+            // What goes over the wire has to come back the same.
+            let sent = serde_json::to_string(&Broadcast::Delta(delta)).unwrap();
+            match serde_json::from_str(&sent).unwrap() {
+                Broadcast::Delta(delta) => viewer.apply(&delta),
+                Broadcast::Snapshot(_) => panic!("a delta came back as a snapshot"),
+            }
+            assert_eq!(viewer, next);
+        }
+        assert!(food_moved, "nobody ate anything");
+        assert!(viewer.ended.is_some());
+    }
+
+    #[test]
+    fn a_turn_reaches_viewers_before_the_snake_moves() {
+        let mut simulation = Simulation::new(Board::new(12, 10, Boundary::Wrap), 3);
+        let before = View::of(&simulation);
+        let turn = match simulation.snake().dir {
+            Direction::Up | Direction::Down => Direction::Left,
+            Direction::Left | Direction::Right => Direction::Up,
+        };
+        simulation.steer_player(0, turn);
+        let after = View::of(&simulation);
+
+        let mut viewer = before.clone();
+        viewer.apply(&before.delta(&after));
+        assert_eq!(viewer, after);
+        assert_eq!(viewer.snakes[0].dir, turn);
+    }
+
+    #[test]
+    fn a_new_game_is_more_than_a_delta_can_describe() {
+        let board = Board::new(12, 10, Boundary::Wrap);
+        let before = View::of(&Simulation::with_players(board.clone(), 2, 3));
+        let mut simulation = Simulation::with_players(board, 1, 4);
+        simulation.step(None);
+        let after = View::of(&simulation);
+
+        let mut viewer = before.clone();
+        viewer.apply(&before.delta(&after));
+        assert_ne!(viewer, after);
+    }
+}
@@ -0,0 +1,424 @@
+use crate::components::board::Board;
+use crate::components::controller::Controller;
+use crate::components::direction::Direction;
+use crate::components::grid_position::GridPosition;
+use crate::components::replay::Replay;
+use crate::components::simulation::Simulation;
+use crate::{try_board, try_controllers, LEVEL, SNAKE_CONFIG};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor};
+use crossterm::{cursor, queue, terminal};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// The game played in a terminal instead of a window, for when there is no
+/// display, such as over SSH. The rules are the same `Simulation` as ever; we
+/// just draw it with colored character cells and read the keyboard through
+/// crossterm.
+///
+/// Characters are about twice as tall as they are wide, so each cell of the
+/// board is drawn two characters wide to keep it square. If the terminal is too
+/// narrow for that we fall back to one character per cell, and if the board
+/// still doesn't fit we show the part of it around the first snake's head.
+pub struct TerminalGame {
+    simulation: Simulation,
+    /// The bot steering each player's snake, or `None` where a person is
+    controllers: Vec<Option<Box<dyn Controller>>>,
+    recording: Replay,
+    record_dir: Option<PathBuf>,
+    /// Printing would land in the middle of the board, so anything that goes
+    /// wrong is shown under it instead.
+    error: Option<String>,
+    last_update: Instant,
+}
+
+/// How the board fits in the terminal.
+struct Layout {
+    /// How many characters wide each cell is drawn.
+    cell_width: u16,
+    /// The first column and row of the board that is shown.
+    left: u16,
+    top: u16,
+    /// How many of the board's columns and rows are shown.
+    columns: u16,
+    rows: u16,
+}
+
+impl Layout {
+    /// Fit `board` into `width` by `height` characters, or `None` if there
+    /// isn't room for anything. When the board doesn't fit, the part we show
+    /// follows `head`.
+    fn new(board: &Board, head: GridPosition, width: u16, height: u16) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let cell_width = if width / 2 >= board.width { 2 } else { 1 };
+        let columns = (width / cell_width).min(board.width);
+        let rows = height.min(board.height);
+        let follow =
+            |pos: u16, shown: u16, size: u16| pos.saturating_sub(shown / 2).min(size - shown);
+        Some(Layout {
+            cell_width,
+            left: follow(head.x, columns, board.width),
+            top: follow(head.y, rows, board.height),
+            columns,
+            rows,
+        })
+    }
+
+    /// Where the cell at `pos` is among the ones shown, row by row, or `None`
+    /// if it is off screen.
+    fn index(&self, pos: GridPosition) -> Option<usize> {
+        let column = pos.x.checked_sub(self.left).filter(|&x| x < self.columns)?;
+        let row = pos.y.checked_sub(self.top).filter(|&y| y < self.rows)?;
+        Some(row as usize * self.columns as usize + column as usize)
+    }
+}
+
+impl TerminalGame {
+    /// Lines above the board for the scores, and below it for messages.
+    const STATUS_LINES: u16 = 1;
+    const MESSAGE_LINES: u16 = 1;
+
+    /// Set up a game the same way the window does, with a snake for each of
+    /// `players`, the second steering with WASD and any more played by bots.
+    pub fn new(seed: u64, record_dir: Option<PathBuf>, players: usize) -> Self {
+        TerminalGame {
+            simulation: Simulation::with_players(try_board(LEVEL.as_ref(), players), players, seed),
+            controllers: try_controllers(players),
+            recording: Replay::new(
+                seed,
+                SNAKE_CONFIG.get().as_ref().clone(),
+                LEVEL.clone(),
+                players,
+            ),
+            record_dir,
+            error: None,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Take over the terminal and play until the player quits, putting the
+    /// terminal back the way it was afterwards, even if something goes wrong.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut out = io::stdout();
+        let screen = RawScreen::enter(&mut out)?;
+        let interval = Duration::from_secs_f32(1.0 / SNAKE_CONFIG.get().updates_per_second);
+        self.draw(&mut out)?;
+        loop {
+            // We wait for keys until it's time for the next tick.
+            let wait = interval.saturating_sub(self.last_update.elapsed());
+            if event::poll(wait)? {
+                match event::read()? {
+                    Event::Key(key) if key.kind != KeyEventKind::Release && !self.key_down(key) => {
+                        break;
+                    }
+                    // Whatever was on screen is in the wrong place now.
+                    Event::Resize(..) => {
+                        queue!(out, ResetColor, terminal::Clear(terminal::ClearType::All))?;
+                    }
+                    _ => {}
+                }
+            }
+            if self.last_update.elapsed() >= interval {
+                self.update();
+                self.last_update = Instant::now();
+            }
+            self.draw(&mut out)?;
+        }
+        // A game left unfinished is saved as it stands, like closing the window.
+        if !self.simulation.is_over() {
+            self.save_recording();
+        }
+        drop(screen);
+        if let Some(error) = &self.error {
+            eprintln!("{}", error);
+        }
+        Ok(())
+    }
+
+    fn update(&mut self) {
+        if self.simulation.is_over() {
+            return;
+        }
+        for (player, controller) in self.controllers.iter_mut().enumerate() {
+            if let Some(controller) = controller {
+                if self.simulation.snakes()[player].is_alive() {
+                    let dir = controller.next_dir(&self.simulation, player);
+                    self.recording.record(&self.simulation, player, dir);
+                    self.simulation.steer_player(player, dir);
+                }
+            }
+        }
+        self.simulation.step(None);
+        if self.simulation.is_over() {
+            self.save_recording();
+        }
+    }
+
+    /// Steer with the arrow keys, and WASD for the second player. Gives whether
+    /// to keep playing.
+    fn key_down(&mut self, key: KeyEvent) -> bool {
+        let ctrl_c =
+            key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl_c || key.code == KeyCode::Esc || key.code == KeyCode::Char('q') {
+            return false;
+        }
+        let steering = match key.code {
+            KeyCode::Up => Some((0, Direction::Up)),
+            KeyCode::Down => Some((0, Direction::Down)),
+            KeyCode::Left => Some((0, Direction::Left)),
+            KeyCode::Right => Some((0, Direction::Right)),
+            KeyCode::Char('w') => Some((1, Direction::Up)),
+            KeyCode::Char('s') => Some((1, Direction::Down)),
+            KeyCode::Char('a') => Some((1, Direction::Left)),
+            KeyCode::Char('d') => Some((1, Direction::Right)),
+            _ => None,
+        };
+        match steering {
+            Some((player, dir)) if player < self.simulation.players() => {
+                // Bots don't take orders from the keyboard.
+                let is_bot = self.controllers.get(player).is_some_and(Option::is_some);
+                if !is_bot && !self.simulation.is_over() {
+                    self.recording.record(&self.simulation, player, dir);
+                    self.simulation.steer_player(player, dir);
+                }
+            }
+            // Every new game gets a fresh seed of its own.
+            _ if key.code == KeyCode::Enter && self.simulation.is_over() => {
+                self.restart(rand::random())
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn restart(&mut self, seed: u64) {
+        let players = self.simulation.players();
+        let board = try_board(LEVEL.as_ref(), players);
+        self.simulation = Simulation::with_players(board, players, seed);
+        self.recording = Replay::new(
+            seed,
+            SNAKE_CONFIG.get().as_ref().clone(),
+            LEVEL.clone(),
+            players,
+        );
+        self.last_update = Instant::now();
+    }
+
+    /// Write the game played so far to `record_dir`, named after its seed.
+    fn save_recording(&mut self) {
+        if let Some(dir) = &self.record_dir {
+            self.recording.finish(&self.simulation);
+            let path = dir.join(format!("snake-{}.json", self.recording.seed));
+            if let Err(e) = self.recording.save(&path) {
+                self.error = Some(format!("Couldn't save replay to {}: {}", path.display(), e));
+            }
+        }
+    }
+
+    /// Work out how to fit the board into a terminal `width` by `height`
+    /// characters, or `None` if there isn't room for anything.
+    fn layout(&self, width: u16, height: u16) -> Option<Layout> {
+        let height = height.checked_sub(Self::STATUS_LINES + Self::MESSAGE_LINES)?;
+        Layout::new(
+            self.simulation.board(),
+            self.simulation.snake().head.pos,
+            width,
+            height,
+        )
+    }
+
+    /// The color of every cell `layout` shows, by `Layout::index`, from the
+    /// same config colors the window uses. Things are colored in the order the
+    /// window draws them, so whatever is drawn last ends up on top.
+    fn colors(&self, layout: &Layout) -> Vec<[f32; 4]> {
+        let config = SNAKE_CONFIG.get();
+        let board = self.simulation.board();
+        let mut colors = vec![config.background; layout.columns as usize * layout.rows as usize];
+        let mut paint = |pos: GridPosition, color: [f32; 4]| {
+            if let Some(i) = layout.index(pos) {
+                colors[i] = color;
+            }
+        };
+        for &wall in &board.walls {
+            paint(wall, config.wall);
+        }
+        for (player, snake) in self.simulation.snakes().iter().enumerate() {
+            let (head, body) = config.snake_colors(player);
+            for segment in &snake.body {
+                paint(segment.pos, body);
+            }
+            paint(snake.head.pos, head);
+        }
+        if !self.simulation.has_won() {
+            paint(self.simulation.food().pos, config.food);
+        }
+        colors
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let (width, height) = terminal::size()?;
+        let layout = match self.layout(width, height) {
+            Some(layout) => layout,
+            None => {
+                queue!(out, cursor::MoveTo(0, 0), ResetColor, Print("Too small"))?;
+                return out.flush();
+            }
+        };
+
+        let scores: Vec<String> = (0..self.simulation.players())
+            .map(|player| format!("P{} {}", player + 1, self.simulation.score_of(player)))
+            .collect();
+        let status = format!("Snake! {}", scores.join("  "));
+        queue!(
+            out,
+            cursor::MoveTo(0, 0),
+            ResetColor,
+            Print(fit(&status, width))
+        )?;
+
+        let board = self.simulation.board();
+        let colors = self.colors(&layout);
+        let cell = " ".repeat(layout.cell_width as usize);
+        for (row, line) in (0..).zip(colors.chunks(layout.columns as usize)) {
+            queue!(out, cursor::MoveTo(0, Self::STATUS_LINES + row))?;
+            let mut current = None;
+            for &color in line {
+                let color = to_color(color);
+                if current != Some(color) {
+                    queue!(out, SetBackgroundColor(color))?;
+                    current = Some(color);
+                }
+                queue!(out, Print(&cell))?;
+            }
+            queue!(
+                out,
+                ResetColor,
+                terminal::Clear(terminal::ClearType::UntilNewLine)
+            )?;
+        }
+
+        let message = if let Some(error) = &self.error {
+            error.clone()
+        } else if self.simulation.is_over() {
+            let ending = match self.simulation.winner() {
+                _ if self.simulation.has_won() => String::from("Board full, you win!"),
+                Some(winner) => format!("Player {} wins!", winner + 1),
+                None if self.simulation.players() > 1 => String::from("It's a draw!"),
+                None => format!("Score {}.", self.simulation.score()),
+            };
+            format!("{} Enter to play again, Esc to quit.", ending)
+        } else if layout.columns < board.width || layout.rows < board.height {
+            String::from("Too big to show, following P1.")
+        } else {
+            String::new()
+        };
+        queue!(
+            out,
+            cursor::MoveTo(0, Self::STATUS_LINES + layout.rows),
+            Print(fit(&message, width)),
+            terminal::Clear(terminal::ClearType::FromCursorDown)
+        )?;
+        out.flush()
+    }
+}
+
+/// A line of text padded or cut to exactly `width` characters, so it covers
+/// whatever was there before.
+fn fit(text: &str, width: u16) -> String {
+    format!("{:width$.width$}", text, width = width as usize)
+}
+
+/// A config color as a terminal color, ignoring its alpha.
+fn to_color(color: [f32; 4]) -> Color {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    Color::Rgb {
+        r: channel(color[0]),
+        g: channel(color[1]),
+        b: channel(color[2]),
+    }
+}
+
+/// The terminal in raw mode on its alternate screen for as long as this lives.
+struct RawScreen;
+
+impl RawScreen {
+    fn enter(out: &mut impl Write) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        queue!(
+            out,
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+        out.flush()?;
+        Ok(RawScreen)
+    }
+}
+
+impl Drop for RawScreen {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        let _ = queue!(
+            out,
+            ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = out.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::board::Boundary;
+
+    #[test]
+    fn a_board_that_fits_is_drawn_whole_two_characters_a_cell() {
+        let board = Board::new(20, 10, Boundary::Wrap);
+        let layout = Layout::new(&board, (19, 9).into(), 80, 22).unwrap();
+        assert_eq!(layout.cell_width, 2);
+        assert_eq!((layout.left, layout.top), (0, 0));
+        assert_eq!((layout.columns, layout.rows), (20, 10));
+        assert_eq!(layout.index((19, 9).into()), Some(199));
+
+        // Too narrow for two characters, but not for one.
+        let layout = Layout::new(&board, (0, 0).into(), 30, 22).unwrap();
+        assert_eq!(layout.cell_width, 1);
+        assert_eq!((layout.columns, layout.rows), (20, 10));
+    }
+
+    #[test]
+    fn a_board_bigger_than_the_terminal_follows_the_head() {
+        let board = Board::new(100, 50, Boundary::Wrap);
+        let shown = |head: (u16, u16)| {
+            let layout = Layout::new(&board, head.into(), 40, 10).unwrap();
+            assert_eq!(layout.cell_width, 1);
+            assert_eq!((layout.columns, layout.rows), (40, 10));
+            (layout.left, layout.top)
+        };
+        // Kept in the middle, unless that would show past an edge.
+        assert_eq!(shown((50, 25)), (30, 20));
+        assert_eq!(shown((0, 0)), (0, 0));
+        assert_eq!(shown((10, 3)), (0, 0));
+        assert_eq!(shown((99, 49)), (60, 40));
+        assert_eq!(shown((90, 47)), (60, 40));
+
+        let layout = Layout::new(&board, (50, 25).into(), 40, 10).unwrap();
+        assert_eq!(layout.index((30, 20).into()), Some(0));
+        assert_eq!(layout.index((69, 29).into()), Some(399));
+        assert_eq!(layout.index((29, 20).into()), None);
+        assert_eq!(layout.index((50, 30).into()), None);
+    }
+
+    #[test]
+    fn there_is_no_layout_without_room() {
+        let board = Board::new(20, 10, Boundary::Wrap);
+        assert!(Layout::new(&board, (0, 0).into(), 0, 10).is_none());
+        assert!(Layout::new(&board, (0, 0).into(), 80, 0).is_none());
+    }
+}
@@ -0,0 +1,372 @@
+use crate::components::campaign::Campaign;
+use crate::components::controller::ControllerKind;
+use crate::components::level::Level;
+use crate::components::netcode::Setup;
+use crate::SnakeConfig;
+use std::fmt;
+use std::path::Path;
+
+/// The smallest board we play on. Anything smaller has no room for the snakes
+/// to start out facing each other.
+pub const MIN_GRID_SIZE: u16 = 4;
+/// The biggest board we play on. Every cell costs memory and time to look at,
+/// for the bots most of all, so a board can't be as big as a `u16` allows.
+pub const MAX_GRID_SIZE: u16 = 1000;
+/// The longest a key can take to have an effect in a networked game, in ticks.
+pub const MAX_INPUT_DELAY: u64 = 60;
+/// The most players a game can have, one for each spawn on the board.
+pub const MAX_PLAYERS: usize = 4;
+
+/// What can go wrong setting up the config.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config couldn't be read at all, such as a file that isn't valid TOML
+    /// or a value of the wrong type.
+    Unreadable(String),
+    /// The config was read, but some of its values don't make sense.
+    Invalid(Vec<FieldError>),
+}
+
+/// A value in the config that doesn't make sense, and why.
+#[derive(Debug)]
+pub struct FieldError {
+    /// The name of the setting, as written in the config file.
+    pub field: &'static str,
+    pub problem: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.problem)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Unreadable(e) => write!(f, "Couldn't read the config: {}", e),
+            ConfigError::Invalid(problems) => {
+                write!(f, "The config has {} problem(s):", problems.len())?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Check every value in `config`, gathering up all the problems with it rather
+/// than stopping at the first, so they can all be fixed in one go.
+pub fn validate(config: &SnakeConfig) -> Result<(), ConfigError> {
+    let mut problems = values(config);
+
+    // Levels and campaigns have to fit the board, so we load them to be sure.
+    if let Some(path) = &config.level {
+        match Level::load(Path::new(path), config) {
+            Ok(level) => {
+                if let Err(e) = config.board_for(Some(&level), config.players) {
+                    problems.add("players", format!("{} on level {}", e, path));
+                }
+            }
+            Err(e) => problems.add("level", format!("{}: {}", path, e)),
+        }
+    }
+    if let Some(path) = &config.campaign {
+        if let Err(e) = Campaign::load(Path::new(path), config) {
+            problems.add("campaign", format!("{}: {}", path, e));
+        }
+    }
+
+    problems.into_result()
+}
+
+/// Check a config that came from another machine, such as the one a replay was
+/// recorded under. The level comes along with it, so the files it names there
+/// aren't looked for here.
+pub fn validate_received(config: &SnakeConfig) -> Result<(), ConfigError> {
+    values(config).into_result()
+}
+
+/// Check the `setup` the host of a networked game sent us before playing by it.
+pub fn validate_setup(setup: &Setup) -> Result<(), ConfigError> {
+    let mut problems = Problems::default();
+    problems.grid_size("grid_width", setup.grid_width);
+    problems.grid_size("grid_height", setup.grid_height);
+    problems.updates_per_second(setup.updates_per_second);
+    problems.check(
+        "players",
+        (1..=MAX_PLAYERS).contains(&setup.players),
+        || format!("must be from 1 to {}, not {}", MAX_PLAYERS, setup.players),
+    );
+    problems.at_most("input_delay", setup.input_delay, MAX_INPUT_DELAY);
+    problems.into_result()
+}
+
+/// The problems with the values in `config` on their own, without the files
+/// it names.
+fn values(config: &SnakeConfig) -> Problems {
+    let mut problems = Problems::default();
+
+    problems.grid_size("grid_width", config.grid_width);
+    problems.grid_size("grid_height", config.grid_height);
+    problems.at_least("max_window_width", config.max_window_width, 1);
+    problems.at_least("max_window_height", config.max_window_height, 1);
+    // A cell has to fit in the window for the snakes to be seen at all.
+    problems.at_least("cell_width", config.cell_width, 1);
+    problems.at_most("cell_width", config.cell_width, config.max_window_width);
+    problems.at_least("cell_height", config.cell_height, 1);
+    problems.at_most("cell_height", config.cell_height, config.max_window_height);
+    problems.updates_per_second(config.updates_per_second);
+
+    problems.color("background", config.background);
+    problems.color("snake_head", config.snake_head);
+    problems.color("snake_body", config.snake_body);
+    problems.color("snake2_head", config.snake2_head);
+    problems.color("snake2_body", config.snake2_body);
+    problems.color("snake3_head", config.snake3_head);
+    problems.color("snake3_body", config.snake3_body);
+    problems.color("snake4_head", config.snake4_head);
+    problems.color("snake4_body", config.snake4_body);
+    problems.color("food", config.food);
+    problems.color("wall", config.wall);
+
+    problems.check(
+        "players",
+        (1..=MAX_PLAYERS).contains(&config.players),
+        || format!("must be from 1 to {}, not {}", MAX_PLAYERS, config.players),
+    );
+    problems.at_least("rounds_to_win", config.rounds_to_win, 1);
+    problems.check(
+        "controllers",
+        config.controllers.len() <= MAX_PLAYERS,
+        || {
+            format!(
+                "can list at most {} players, not {}",
+                MAX_PLAYERS,
+                config.controllers.len()
+            )
+        },
+    );
+    problems.check(
+        "bot_command",
+        match &config.bot_command {
+            Some(command) => !command.trim().is_empty(),
+            None => !config.controllers.contains(&ControllerKind::External),
+        },
+        || String::from("must be set to a program to run for external bots"),
+    );
+    problems.at_least("bot_timeout_ms", config.bot_timeout_ms, 1);
+    problems.at_least("battlesnake_timeout_ms", config.battlesnake_timeout_ms, 1);
+    problems.at_most("input_delay", config.input_delay, MAX_INPUT_DELAY);
+    problems.at_least("net_timeout_ms", config.net_timeout_ms, 1);
+    problems.at_least("max_rollback", config.max_rollback, 1);
+    problems.at_most("max_rollback", config.max_rollback, 60);
+    problems.check(
+        "conditions.packet_loss",
+        (0.0..1.0).contains(&config.conditions.packet_loss),
+        || {
+            format!(
+                "must be at least 0 and less than 1, not {}",
+                config.conditions.packet_loss
+            )
+        },
+    );
+
+    problems.check(
+        "skin",
+        !config
+            .skin
+            .as_ref()
+            .is_some_and(|skin| skin.trim().is_empty()),
+        || String::from("must be the path to a skin's folder"),
+    );
+    problems.check("spectator_port", config.spectator_port != Some(0), || {
+        String::from("must be a port from 1 to 65535")
+    });
+
+    problems.finite("rewards.food", config.rewards.food);
+    problems.finite("rewards.death", config.rewards.death);
+    problems.finite("rewards.win", config.rewards.win);
+    problems.finite("rewards.step", config.rewards.step);
+    problems.finite("rewards.closer", config.rewards.closer);
+
+    problems
+}
+
+/// Every problem found with a config so far.
+#[derive(Default)]
+struct Problems(Vec<FieldError>);
+
+impl Problems {
+    fn add(&mut self, field: &'static str, problem: String) {
+        self.0.push(FieldError { field, problem });
+    }
+
+    /// Note a problem with `field` if it isn't `ok`.
+    fn check(&mut self, field: &'static str, ok: bool, problem: impl FnOnce() -> String) {
+        if !ok {
+            self.add(field, problem());
+        }
+    }
+
+    fn at_least<T: PartialOrd + fmt::Display>(&mut self, field: &'static str, value: T, min: T) {
+        if value < min {
+            self.add(field, format!("must be at least {}, not {}", min, value));
+        }
+    }
+
+    fn at_most<T: PartialOrd + fmt::Display>(&mut self, field: &'static str, value: T, max: T) {
+        if value > max {
+            self.add(field, format!("must be at most {}, not {}", max, value));
+        }
+    }
+
+    /// A side of the board, in cells.
+    fn grid_size(&mut self, field: &'static str, size: u16) {
+        self.check(
+            field,
+            (MIN_GRID_SIZE..=MAX_GRID_SIZE).contains(&size),
+            || {
+                format!(
+                    "must be from {} to {}, not {}",
+                    MIN_GRID_SIZE, MAX_GRID_SIZE, size
+                )
+            },
+        );
+    }
+
+    fn updates_per_second(&mut self, rate: f32) {
+        self.check(
+            "updates_per_second",
+            rate.is_finite() && rate > 0.0 && rate <= 1000.0,
+            || format!("must be more than 0 and at most 1000, not {}", rate),
+        );
+    }
+
+    fn finite(&mut self, field: &'static str, value: f32) {
+        self.check(field, value.is_finite(), || {
+            format!("must be a number, not {}", value)
+        });
+    }
+
+    /// Colors are red, green, blue and alpha, each from 0 to 1.
+    fn color(&mut self, field: &'static str, color: [f32; 4]) {
+        let in_range = color.iter().all(|c| (0.0..=1.0).contains(c));
+        self.check(field, in_range, || {
+            format!("every part must be from 0 to 1, not {:?}", color)
+        });
+    }
+
+    fn into_result(self) -> Result<(), ConfigError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(self.0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::board::Boundary;
+
+    fn problems(result: Result<(), ConfigError>) -> Vec<&'static str> {
+        match result {
+            Ok(()) => Vec::new(),
+            Err(ConfigError::Invalid(problems)) => {
+                problems.into_iter().map(|problem| problem.field).collect()
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn the_default_config_is_fine() {
+        assert!(validate(&crate::default_config()).is_ok());
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let mut config = crate::default_config();
+        config.grid_width = MAX_GRID_SIZE + 1;
+        config.grid_height = MIN_GRID_SIZE - 1;
+        config.updates_per_second = f32::NAN;
+        config.food = [0.0, 2.0, 0.0, 1.0];
+        config.players = MAX_PLAYERS + 1;
+        assert_eq!(
+            problems(validate(&config)),
+            vec![
+                "grid_width",
+                "grid_height",
+                "updates_per_second",
+                "food",
+                "players"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_cell_fits_in_the_window() {
+        let mut config = crate::default_config();
+        config.cell_width = 300;
+        config.max_window_width = 300;
+        assert!(validate(&config).is_ok());
+
+        config.cell_height = config.max_window_height + 1;
+        assert_eq!(problems(validate(&config)), vec!["cell_height"]);
+    }
+
+    #[test]
+    fn a_level_has_a_spawn_for_every_player() {
+        let mut config = crate::default_config();
+        config.level = Some(String::from("levels/box.toml"));
+        assert!(validate(&config).is_ok());
+
+        config.players = 2;
+        assert_eq!(problems(validate(&config)), vec!["players"]);
+        let level = Level::load(Path::new("levels/box.toml"), &config).unwrap();
+        assert!(config.board_for(Some(&level), 1).is_ok());
+        assert_eq!(
+            config.board_for(Some(&level), 2).unwrap_err(),
+            "The board has room for 1 snakes, not 2"
+        );
+    }
+
+    #[test]
+    fn a_received_config_brings_its_level_along() {
+        let mut config = crate::default_config();
+        config.level = Some(String::from("a level on another machine.txt"));
+        assert_eq!(problems(validate(&config)), vec!["level"]);
+        assert!(validate_received(&config).is_ok());
+
+        config.grid_height = u16::MAX;
+        assert_eq!(problems(validate_received(&config)), vec!["grid_height"]);
+    }
+
+    #[test]
+    fn a_hosts_setup_is_checked_too() {
+        let mut setup = Setup {
+            seed: 0,
+            players: 2,
+            input_delay: 2,
+            grid_width: 20,
+            grid_height: 20,
+            boundary: Boundary::Wrap,
+            updates_per_second: 8.0,
+            level: None,
+        };
+        assert!(validate_setup(&setup).is_ok());
+
+        setup.grid_width = u16::MAX;
+        setup.players = 0;
+        setup.input_delay = MAX_INPUT_DELAY + 1;
+        assert_eq!(
+            problems(validate_setup(&setup)),
+            vec!["grid_width", "players", "input_delay"]
+        );
+    }
+}
@@ -4,41 +4,286 @@
 //!
 //! Note that this example is meant to highlight the general
 //! structure of a ggez game. Some of the details may need to
-//! be changed to scale the game. For example, if we needed to
-//! draw hundreds or thousands of shapes, a SpriteBatch is going
-//! to offer far better performance than the direct draw calls
-//! that this example uses.
+//! be changed to scale the game. The board is drawn as a single
+//! mesh that is only rebuilt when something on it moves, rather
+//! than with a draw call per cell, so boards of 200×200 with long
+//! snakes still run at full frame rate. Press F3 to see for yourself.
 //!
 //! Author: @termhn
 //! Original repo: https://github.com/termhn/ggez_snake
 
 use ggez::conf::FullscreenType;
-use ggez::{event, GameResult};
+use ggez::{event, graphics, GameResult};
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 
-use config;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 mod components;
+use components::battlesnake::Engine;
+use components::board::{Board, Boundary};
+use components::campaign::Campaign;
+use components::cli::{Cli, Command, USAGE};
+use components::controller::{Controller, ControllerKind, Hamiltonian};
+use components::environment::{self, Rewards, VecEnvironment};
 use components::game_state::*;
+use components::high_scores::HighScores;
+use components::level::Level;
+use components::live_config::LiveConfig;
+use components::lockstep::Lockstep;
+use components::netcode::{Netcode, NetcodeKind, Setup};
+use components::replay::Replay;
+use components::rollback::{Conditions, Rollback};
+use components::simulation::Simulation;
+use components::spectator::{Spectators, View, Viewer};
+use components::terminal::TerminalGame;
+use components::validation::{validate, validate_received, validate_setup, ConfigError};
 
 #[macro_use]
 extern crate lazy_static;
 
 lazy_static! {
-    pub static ref SNAKE_CONFIG: SnakeConfig = try_config();
-    pub static ref SCREEN_SIZE: (f32, f32) = (
-        SNAKE_CONFIG.grid_width as f32 * SNAKE_CONFIG.cell_width as f32,
-        SNAKE_CONFIG.grid_height as f32 * SNAKE_CONFIG.cell_height as f32,
-    );
+    /// What we were asked to do on the command line. If it doesn't make sense
+    /// we say why and how to use it, and stop.
+    pub static ref CLI: Cli = {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        Cli::parse(&args).unwrap_or_else(|e| {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        })
+    };
+    /// The replay being watched, if the game was started with `replay <file>`.
+    pub static ref REPLAY: Option<Replay> = match &CLI.command {
+        Command::Replay { path, verify: false } => Some(load_replay(path)),
+        _ => None,
+    };
+    /// The networked game joined with `--join <address>`, until the game takes it.
+    /// Joining waits for the host to start the game, which tells us the rules.
+    pub static ref JOINED: Mutex<Option<Box<dyn Netcode>>> =
+        Mutex::new(CLI.join.as_ref().map(|address| join_game(address)));
+    /// The game being watched with `--watch <address>`, until the viewer takes it.
+    pub static ref WATCHING: Mutex<Option<Viewer>> =
+        Mutex::new(CLI.watch.as_ref().map(|address| watch_game(address)));
+    /// A replay is always shown with the config it was recorded under, a
+    /// networked game is played by the host's rules, and a watched game is
+    /// shown on the board it is played on.
+    ///
+    /// A game played from our own config picks up any changes made to it while
+    /// it runs, see `reload_config`.
+    pub static ref SNAKE_CONFIG: LiveConfig = LiveConfig::new(match (&*REPLAY, joined_setup(), watched_view()) {
+        (Some(replay), _, _) => replay.config.clone(),
+        (None, Some(setup), _) => with_bot_arg(try_config()).with_setup(&setup),
+        (None, None, Some(view)) => {
+            let config = try_config().with_view(&view);
+            if let Err(e) = validate_received(&config) {
+                eprintln!("The game being watched can't be shown. {}", e);
+                std::process::exit(1);
+            }
+            config
+        }
+        (None, None, None) => with_bot_arg(try_config()),
+    });
+    /// The level being played, from `level` in the config or `--level <file>`.
+    /// A replay brings its own level along, and the host of a networked game
+    /// sends theirs.
+    pub static ref LEVEL: Option<Level> = match (&*REPLAY, joined_setup()) {
+        (Some(replay), _) => replay.level.clone(),
+        (None, Some(setup)) => setup.level,
+        (None, None) => SNAKE_CONFIG.get().level.clone()
+            .map(|path| {
+                Level::load(Path::new(&path), &SNAKE_CONFIG.get())
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error with level {}: {}", path, e);
+                        std::process::exit(1);
+                    })
+            }),
+    };
+    pub static ref SCREEN_SIZE: (f32, f32) = SNAKE_CONFIG.get().screen_size();
 }
 
 fn main() -> GameResult {
+    if CLI.help {
+        print!("{}", USAGE);
+        return Ok(());
+    }
+
+    // `--check-config` reads the config and reports every problem with it,
+    // without playing.
+    if CLI.check_config {
+        match load_config() {
+            Ok(_) => {
+                println!("{}: OK", config_path().display());
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // `--print-config` shows the config that the defaults, the config file,
+    // the environment and the command line add up to.
+    if CLI.print_config {
+        let config = with_bot_arg(try_config());
+        let text = toml::Value::try_from(&config)
+            .and_then(|value| toml::to_string_pretty(&value))
+            .unwrap_or_else(|e| panic!("Error writing out the config: {}", e));
+        print!("{}", text);
+        return Ok(());
+    }
+
+    // `scores` prints every high score table.
+    if CLI.command == Command::Scores {
+        print_scores(&HighScores::load());
+        return Ok(());
+    }
+
+    // `replay <file> --verify` plays a replay back without opening a window and
+    // checks that it ends the way it was recorded.
+    if let Command::Replay { path, verify: true } = &CLI.command {
+        match load_replay(path).verify() {
+            Ok(()) => {
+                println!("{}: OK", path);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // `--gym <count>` runs that many training environments for an agent in
+    // another process, talking JSON lines over stdin and stdout.
+    if let Some(count) = CLI.gym {
+        let board = try_board(LEVEL.as_ref(), 1);
+        let mut environments = VecEnvironment::new(count, board, SNAKE_CONFIG.get().rewards);
+        if let Err(e) = environment::serve(&mut environments) {
+            eprintln!("Gym environment stopped: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // `--watch <address>:<port>` watches a game someone else is serving to
+    // spectators. The window is sized for their board, so we find out what it
+    // is before taking the viewer.
+    lazy_static::initialize(&SCREEN_SIZE);
+    let viewer = WATCHING
+        .lock()
+        .expect("Nobody else touches WATCHING")
+        .take();
+    if let Some(viewer) = viewer {
+        return run_game(viewer);
+    }
+
+    // The seed for the first game comes from the config, where the `--seed`
+    // flag, the `SNAKE_SEED` env var or `seed` in Config.toml put it, and
+    // failing that we pick one at random.
+    let seed = SNAKE_CONFIG.get().seed.unwrap_or_else(rand::random);
+    // `--check-autopilot` plays a game with the Hamiltonian autopilot without
+    // opening a window and checks that it fills the board.
+    if CLI.check_autopilot {
+        match Hamiltonian::check(try_board(LEVEL.as_ref(), 1), seed) {
+            Ok(ticks) => {
+                println!("Autopilot filled the board in {} ticks", ticks);
+                return Ok(());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // With `--record <dir>` every game played is saved to `dir` as a replay.
+    let record_dir = CLI.record.clone();
+
+    // `simulate` plays a single game between bots without opening a window,
+    // giving up after `--max-ticks` in case they never finish.
+    let simulate = CLI.command == Command::Simulate;
+    let max_ticks = CLI.max_ticks.unwrap_or(100_000);
+    // `--host <port>` hosts a networked game for `--players <n>` players, 2 to 4,
+    // who join it with `--join <address>:<port>`. With `--rollback` both sides
+    // play a two player game with rollback netcode instead of lockstep.
+    let session = if let Some(port) = CLI.host {
+        Some(host_game(port, seed))
+    } else {
+        JOINED.lock().expect("Nobody else touches JOINED").take()
+    };
+    if let Some(session) = session {
+        if simulate {
+            play_network_headless(session, max_ticks, record_dir);
+            return Ok(());
+        }
+        let state = GameState::network(session, record_dir);
+        return run_game(state.with_spectators(serve_spectators()));
+    }
+
+    // `--battlesnake <url>,<url>` plays a game between bots written for the
+    // Battlesnake API, one snake for each url, without opening a window.
+    if let Some(urls) = &CLI.battlesnake {
+        play_battlesnake(seed, urls.clone(), max_ticks, record_dir);
+        return Ok(());
+    }
+    if simulate {
+        play_headless(seed, SNAKE_CONFIG.get().players, max_ticks, record_dir);
+        return Ok(());
+    }
+    // `--versus` starts a two player match, as does `players = 2` in the config.
+    let players = if CLI.versus {
+        2
+    } else {
+        SNAKE_CONFIG.get().players
+    };
+    // `--terminal` plays in the terminal rather than a window, for when there
+    // is no display to open one on.
+    if CLI.terminal {
+        TerminalGame::new(seed, record_dir, players).run()?;
+        return Ok(());
+    }
+    // `--demo` starts with bots playing on their own until someone presses Enter.
+    let demo = CLI.demo;
+
+    // Next we create a new instance of our GameState struct, which implements EventHandler.
+    // It either plays back the replay we were given, plays through a campaign
+    // from the config or `--campaign <file>`, or plays endless games, either
+    // alone or as a versus match, possibly after an attract mode demo.
+    let campaign = SNAKE_CONFIG.get().campaign.clone();
+    let state = match (REPLAY.clone(), campaign) {
+        (Some(replay), _) => GameState::replay(replay).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+        (None, Some(path)) => {
+            let campaign =
+                Campaign::load(Path::new(&path), &SNAKE_CONFIG.get()).unwrap_or_else(|e| {
+                    eprintln!("Error with campaign {}: {}", path, e);
+                    std::process::exit(1);
+                });
+            GameState::campaign(campaign, seed, record_dir)
+        }
+        (None, None) if demo => GameState::demo(seed, record_dir, players),
+        (None, None) => GameState::new(seed, record_dir, players),
+    };
+    // `--spectators <port>` streams every game played to anyone watching.
+    run_game(state.with_spectators(serve_spectators()))
+}
+
+/// Open the window and play `state` in it until it is closed.
+fn run_game<S: event::EventHandler>(mut state: S) -> GameResult {
     let window = ggez::conf::WindowMode {
         width: SCREEN_SIZE.0,
         height: SCREEN_SIZE.1,
         maximized: false,
-        fullscreen_type: FullscreenType::Windowed,
+        fullscreen_type: if SNAKE_CONFIG.get().fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Windowed
+        },
         borderless: false,
         min_width: 0.0,
         max_width: 0.0,
@@ -56,35 +301,744 @@ fn main() -> GameResult {
         // And finally we attempt to build the context and create the window. If it fails, we panic with the message
         // "Failed to build ggez context"
         .build()?;
+    // However big the screen, the board fills it.
+    graphics::set_screen_coordinates(
+        ctx,
+        graphics::Rect::new(0.0, 0.0, SCREEN_SIZE.0, SCREEN_SIZE.1),
+    )?;
 
-    // Next we create a new instance of our GameState struct, which implements EventHandler
-    let state = &mut GameState::new();
     // And finally we actually run our game, passing in our context and state.
-    return event::run(ctx, events_loop, state);
+    event::run(ctx, events_loop, &mut state)
+}
+
+/// `--bot <command>` has the first player's snake steered by an external bot
+/// running `command`, whatever the config says.
+fn with_bot_arg(mut config: SnakeConfig) -> SnakeConfig {
+    if let Some(command) = &CLI.bot {
+        config.bot_command = Some(command.clone());
+        if config.controllers.is_empty() {
+            config.controllers.push(ControllerKind::External);
+        } else {
+            config.controllers[0] = ControllerKind::External;
+        }
+    }
+    config
+}
+
+/// Play one game with a bot steering every snake and print how it went. A bot
+/// that never dies but never eats either would go on forever, so we stop after
+/// `max_ticks`. With a `record_dir` the game is saved there as a replay.
+fn play_headless(seed: u64, players: usize, max_ticks: u64, record_dir: Option<PathBuf>) {
+    let players = players.max(1);
+    let mut simulation =
+        Simulation::with_players(try_board(LEVEL.as_ref(), players), players, seed);
+    let mut recording = Replay::new(
+        seed,
+        SNAKE_CONFIG.get().as_ref().clone(),
+        LEVEL.clone(),
+        players,
+    );
+    let mut controllers: Vec<Box<dyn Controller>> = (0..players)
+        .map(|player| {
+            try_controller(SNAKE_CONFIG.get().controller(player)).unwrap_or_else(|| {
+                panic!(
+                    "Player {} needs a bot in `controllers` to play headless",
+                    player + 1
+                )
+            })
+        })
+        .collect();
+    let mut spectators = serve_spectators();
+
+    while !simulation.is_over() && simulation.ticks() < max_ticks {
+        for (player, controller) in controllers.iter_mut().enumerate() {
+            if simulation.snakes()[player].is_alive() {
+                let dir = controller.next_dir(&simulation, player);
+                recording.record(&simulation, player, dir);
+                simulation.steer_player(player, dir);
+            }
+        }
+        simulation.step(None);
+        show_spectators(&mut spectators, &simulation);
+    }
+    print_results(&simulation);
+    if let Some(dir) = record_dir {
+        save_recording(&dir, recording, &simulation);
+    }
+}
+
+/// Who steers each of the snakes of `players` sharing this computer, or if one
+/// is left without keys to steer with, say so and stop. There are only the
+/// arrow keys and WASD, so a third or fourth player has to be a bot.
+pub fn try_controllers(players: usize) -> Vec<Option<Box<dyn Controller>>> {
+    (0..players)
+        .map(|player| {
+            let controller = try_controller(SNAKE_CONFIG.get().controller(player));
+            if controller.is_none() && player >= 2 {
+                eprintln!(
+                    "Player {} needs a bot in `controllers`, as only two players share the keyboard",
+                    player + 1
+                );
+                std::process::exit(1);
+            }
+            controller
+        })
+        .collect()
+}
+
+/// Host a networked game on `port`, waiting for everyone to join. Everyone plays
+/// on our board, with the food placed from `seed`.
+fn host_game(port: u16, seed: u64) -> Box<dyn Netcode> {
+    let config = SNAKE_CONFIG.get();
+    let netcode = netcode_kind(&config);
+    // Rollback is only ever between two players, and never delays anyone's keys.
+    let players: usize = match netcode {
+        NetcodeKind::Lockstep => CLI.players.unwrap_or(config.players).clamp(2, 4),
+        NetcodeKind::Rollback => 2,
+    };
+    // Everyone needs a spawn of their own on our board.
+    try_board(LEVEL.as_ref(), players);
+    let setup = Setup {
+        seed,
+        players,
+        input_delay: match netcode {
+            NetcodeKind::Lockstep => config.input_delay,
+            NetcodeKind::Rollback => 0,
+        },
+        grid_width: config.grid_width,
+        grid_height: config.grid_height,
+        boundary: config.boundary,
+        updates_per_second: config.updates_per_second,
+        level: LEVEL.clone(),
+    };
+    let timeout = Duration::from_millis(config.net_timeout_ms);
+    let session: std::io::Result<Box<dyn Netcode>> = match netcode {
+        NetcodeKind::Lockstep => {
+            Lockstep::host(port, setup, timeout).map(|session| Box::new(session) as _)
+        }
+        NetcodeKind::Rollback => Rollback::host(
+            port,
+            setup,
+            network_conditions(&config),
+            config.max_rollback,
+            timeout,
+        )
+        .map(|session| Box::new(session) as _),
+    };
+    session.unwrap_or_else(|e| {
+        eprintln!("Couldn't host a game on port {}: {}", port, e);
+        std::process::exit(1);
+    })
+}
+
+/// Join the networked game at `address`. We only get the rest of the config
+/// from the host once we've joined, so until then we go by our own.
+fn join_game(address: &str) -> Box<dyn Netcode> {
+    let config = try_config();
+    let session: std::io::Result<Box<dyn Netcode>> = match netcode_kind(&config) {
+        NetcodeKind::Lockstep => Lockstep::join(address).map(|session| Box::new(session) as _),
+        NetcodeKind::Rollback => Rollback::join(
+            address,
+            network_conditions(&config),
+            config.max_rollback,
+            Duration::from_millis(config.net_timeout_ms),
+        )
+        .map(|session| Box::new(session) as _),
+    };
+    let session = session.unwrap_or_else(|e| {
+        eprintln!("Couldn't join the game at {}: {}", address, e);
+        std::process::exit(1);
+    });
+    // We're about to play by the host's rules, so they had better make sense.
+    if let Err(e) = validate_setup(session.setup()) {
+        eprintln!("The game at {} can't be played. {}", address, e);
+        std::process::exit(1);
+    }
+    session
+}
+
+/// The netcode to play a networked game with: rollback with `--rollback`, and
+/// otherwise whatever `netcode` in the config says.
+fn netcode_kind(config: &SnakeConfig) -> NetcodeKind {
+    if CLI.rollback {
+        NetcodeKind::Rollback
+    } else {
+        config.netcode
+    }
+}
+
+/// The bad network to simulate for rollback, from `[conditions]` in the config
+/// with `--latency <ms>`, `--jitter <ms>` and `--loss <fraction>` taking over.
+fn network_conditions(config: &SnakeConfig) -> Conditions {
+    let mut conditions = config.conditions;
+    if let Some(latency) = CLI.latency {
+        conditions.latency_ms = latency;
+    }
+    if let Some(jitter) = CLI.jitter {
+        conditions.jitter_ms = jitter;
+    }
+    if let Some(loss) = CLI.loss {
+        conditions.packet_loss = loss;
+    }
+    conditions
+}
+
+/// The setup of the networked game we joined, if we joined one.
+fn joined_setup() -> Option<Setup> {
+    JOINED
+        .lock()
+        .expect("Nobody else touches JOINED")
+        .as_ref()
+        .map(|session| session.setup().clone())
+}
+
+/// Play a networked game without opening a window, with the first of the
+/// config's controllers playing our snake, and print how it went.
+fn play_network_headless(
+    mut session: Box<dyn Netcode>,
+    max_ticks: u64,
+    record_dir: Option<PathBuf>,
+) {
+    let player = session.player();
+    let setup = session.setup().clone();
+    let mut simulation = Simulation::with_players(
+        try_board(LEVEL.as_ref(), setup.players),
+        setup.players,
+        setup.seed,
+    );
+    let mut recording = Replay::new(
+        setup.seed,
+        SNAKE_CONFIG.get().as_ref().clone(),
+        LEVEL.clone(),
+        setup.players,
+    );
+    let mut bot = try_controller(SNAKE_CONFIG.get().controller(0)).unwrap_or_else(|| {
+        eprintln!("Our snake needs a bot in `controllers` to play headless");
+        std::process::exit(1);
+    });
+    let mut spectators = serve_spectators();
+
+    while !session.is_settled(&simulation) && simulation.ticks() < max_ticks {
+        if session.needs_input(&simulation) && simulation.snakes()[player].is_alive() {
+            session.steer(bot.next_dir(&simulation, player));
+        }
+        match session.tick(&mut simulation, &mut recording) {
+            Ok(true) => show_spectators(&mut spectators, &simulation),
+            Ok(false) => std::thread::sleep(Duration::from_millis(1)),
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            }
+        }
+    }
+    println!("Played as Player {}", player + 1);
+    if let Some(summary) = session.summary() {
+        println!("{}", summary);
+    }
+    print_results(&simulation);
+    if let Some(dir) = record_dir {
+        save_recording(&dir, recording, &simulation);
+    }
+}
+
+/// Start streaming games to spectators on the port given with `--spectators`
+/// or `spectator_port` in the config, if there is one.
+fn serve_spectators() -> Option<Spectators> {
+    let port = CLI.spectators.or(SNAKE_CONFIG.get().spectator_port)?;
+    match Spectators::serve(port) {
+        Ok(spectators) => Some(spectators),
+        Err(e) => {
+            eprintln!("Couldn't serve spectators on port {}: {}", port, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Show a game played without a window to anyone watching. Bots play far
+/// faster than anyone could follow, so while someone is watching each tick
+/// takes as long as it would in a window. With nobody connected they play on
+/// at full speed.
+fn show_spectators(spectators: &mut Option<Spectators>, simulation: &Simulation) {
+    if let Some(spectators) = spectators {
+        spectators.update(simulation);
+        if !spectators.is_watched() {
+            return;
+        }
+        std::thread::sleep(Duration::from_secs_f32(
+            1.0 / SNAKE_CONFIG.get().updates_per_second,
+        ));
+    }
+}
+
+fn watch_game(address: &str) -> Viewer {
+    Viewer::connect(address).unwrap_or_else(|e| {
+        eprintln!("Couldn't watch the game at {}: {}", address, e);
+        std::process::exit(1);
+    })
+}
+
+/// The game being watched as the viewer first saw it, if we're watching one.
+fn watched_view() -> Option<View> {
+    WATCHING
+        .lock()
+        .expect("Nobody else touches WATCHING")
+        .as_ref()
+        .map(|viewer| viewer.view().clone())
+}
+
+/// Play one game between the Battlesnake bots at `urls` and print how it went,
+/// saving it as a replay in `record_dir` if there is one.
+fn play_battlesnake(seed: u64, urls: Vec<String>, max_ticks: u64, record_dir: Option<PathBuf>) {
+    let players = urls.len();
+    let board = try_board(LEVEL.as_ref(), players);
+    let timeout = Duration::from_millis(SNAKE_CONFIG.get().battlesnake_timeout_ms);
+    let mut engine = Engine::new(board, urls, timeout).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let mut recording = Replay::new(
+        seed,
+        SNAKE_CONFIG.get().as_ref().clone(),
+        LEVEL.clone(),
+        players,
+    );
+    let mut spectators = serve_spectators();
+    let simulation = engine.play(seed, max_ticks, &mut recording, spectators.as_mut());
+    print_results(&simulation);
+    if let Some(dir) = record_dir {
+        save_recording(&dir, recording, &simulation);
+    }
+}
+
+/// Print how each player did in a game played without a window.
+fn print_results(simulation: &Simulation) {
+    for player in 0..simulation.players() {
+        let snake = &simulation.snakes()[player];
+        println!(
+            "Player {}: score {}, length {}, {}",
+            player + 1,
+            simulation.score_of(player),
+            snake.len(),
+            match snake.ate {
+                Some(ate) if ate.is_fatal() => format!("ate {:?}", ate),
+                _ => String::from("survived"),
+            }
+        );
+    }
+    let ending = if simulation.has_won() {
+        ", board full"
+    } else if !simulation.is_over() {
+        ", stopped unfinished"
+    } else {
+        ""
+    };
+    println!(
+        "Seed {}, {} ticks{}",
+        simulation.seed(),
+        simulation.ticks(),
+        ending
+    );
+}
+
+/// Print every high score table, a board size and speed at a time.
+fn print_scores(high_scores: &HighScores) {
+    let tables = high_scores.tables();
+    if tables.is_empty() {
+        println!("No scores yet.");
+    }
+    for table in tables {
+        let first = table[0];
+        println!(
+            "{}x{} {:?} at {} moves a second{}",
+            first.grid_width,
+            first.grid_height,
+            first.boundary,
+            first.updates_per_second,
+            first
+                .level
+                .as_ref()
+                .map_or(String::new(), |level| format!(", {}", level))
+        );
+        for (rank, entry) in table.iter().enumerate() {
+            println!(
+                "{:>2}. {:<12} {:>4} long {:>4} {:>3}:{:02} {}",
+                rank + 1,
+                entry.name,
+                entry.score,
+                entry.length,
+                entry.duration as u64 / 60,
+                entry.duration as u64 % 60,
+                entry.day()
+            );
+        }
+        println!();
+    }
+}
+
+/// Save a game played without a window to `dir` as a replay, to be watched later.
+fn save_recording(dir: &Path, mut recording: Replay, simulation: &Simulation) {
+    recording.finish(simulation);
+    let path = dir.join(format!("snake-{}.json", simulation.seed()));
+    if let Err(e) = recording.save(&path) {
+        eprintln!("Couldn't save replay to {}: {}", path.display(), e);
+    }
+}
+
+/// Where we keep the file `name` in the user's data directory, if the platform
+/// gives us one.
+pub fn data_file(name: &str) -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "Gray Olson", "snake").map(|dirs| dirs.data_dir().join(name))
+}
+
+/// The replay saved at `path`, or if it can't be read or the config it was
+/// recorded under doesn't make sense, say why and stop.
+fn load_replay(path: &str) -> Replay {
+    let replay = Replay::load(Path::new(path)).unwrap_or_else(|e| {
+        eprintln!("Couldn't read replay {}: {}", path, e);
+        std::process::exit(1);
+    });
+    if let Err(e) = validate_received(&replay.config) {
+        eprintln!("Replay {} can't be shown. {}", path, e);
+        std::process::exit(1);
+    }
+    replay
+}
+
+/// The board for a game of `players` to play on, laid out by `level` if there
+/// is one, or if the level doesn't fit the config or has too few spawns, say
+/// why and stop.
+pub fn try_board(level: Option<&Level>, players: usize) -> Board {
+    SNAKE_CONFIG
+        .get()
+        .board_for(level, players)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+}
+
+/// The bot that plays as `kind`, or `None` for a person, or if the bot can't
+/// be had, say why and stop.
+pub fn try_controller(kind: ControllerKind) -> Option<Box<dyn Controller>> {
+    kind.build().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
 }
 
+/// The config file the game is set up with unless `--config` says otherwise.
+pub const CONFIG_FILE: &str = "Config.toml";
+
+/// The config file the game is set up with, which is watched for changes
+/// while playing.
+pub fn config_path() -> PathBuf {
+    CLI.config
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(CONFIG_FILE))
+}
+
+/// The config the game comes with, which is where anything left out of
+/// `CONFIG_FILE`, or the whole thing if there isn't one, comes from.
+const DEFAULT_CONFIG: &str = include_str!("../Config.toml");
+
+/// The config `Config.toml` ships with, for tests that need one.
+#[cfg(test)]
+pub fn default_config() -> SnakeConfig {
+    toml::from_str(DEFAULT_CONFIG).expect("Config.toml is a whole config")
+}
+
+/// The config to play with, or if it has problems, say what they are and stop.
 fn try_config() -> SnakeConfig {
+    load_config().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+/// The config built up from the defaults, then the config file if there is one,
+/// then any `SNAKE_` environment variables, and finally the command line, each
+/// taking over from the last. It is checked to make sense once it's all there.
+fn load_config() -> Result<SnakeConfig, ConfigError> {
+    let unreadable = |e: config::ConfigError| ConfigError::Unreadable(e.to_string());
     let mut settings = config::Config::default();
     settings
-        .merge(config::File::with_name("Config"))
-        .expect("Error with Config.toml");
+        .merge(config::File::from_str(
+            DEFAULT_CONFIG,
+            config::FileFormat::Toml,
+        ))
+        .map_err(unreadable)?;
     settings
-        .merge(config::Environment::with_prefix("SNAKE"))
-        .expect("Error with SNAKE prefix Environment Vars");
+        .merge(
+            config::File::from(config_path())
+                .format(config::FileFormat::Toml)
+                // Without `--config` we're happy with the defaults, but a file
+                // asked for has to be there.
+                .required(CLI.config.is_some()),
+        )
+        .map_err(unreadable)?;
     settings
-        .try_into::<SnakeConfig>()
-        .expect("Error Related to Mapping config to Snake Struct")
+        .merge(config::Environment::with_prefix("SNAKE"))
+        .map_err(unreadable)?;
+    let mut config = settings.try_into::<SnakeConfig>().map_err(unreadable)?;
+    CLI.apply(&mut config);
+    validate(&config)?;
+    Ok(config)
+}
+
+/// Read the config again after it has been edited, set up the same way it was
+/// when the game started.
+pub fn reload_config() -> Result<SnakeConfig, String> {
+    load_config().map(with_bot_arg).map_err(|e| e.to_string())
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct SnakeConfig {
-    pub grid_width: u8,
-    pub grid_height: u8,
-    pub cell_width: u8,
-    pub cell_height: u8,
+    pub grid_width: u16,
+    pub grid_height: u16,
+    pub cell_width: u16,
+    pub cell_height: u16,
     pub updates_per_second: f32,
     pub background: [f32; 4],
     pub snake_head: [f32; 4],
     pub snake_body: [f32; 4],
+    /// The second player's snake in a versus game.
+    #[serde(default = "default_snake2_head_color")]
+    pub snake2_head: [f32; 4],
+    #[serde(default = "default_snake2_body_color")]
+    pub snake2_body: [f32; 4],
+    /// The third and fourth players' snakes in a networked game.
+    #[serde(default = "default_snake3_head_color")]
+    pub snake3_head: [f32; 4],
+    #[serde(default = "default_snake3_body_color")]
+    pub snake3_body: [f32; 4],
+    #[serde(default = "default_snake4_head_color")]
+    pub snake4_head: [f32; 4],
+    #[serde(default = "default_snake4_body_color")]
+    pub snake4_body: [f32; 4],
     pub food: [f32; 4],
+    #[serde(default = "default_wall_color")]
+    pub wall: [f32; 4],
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub boundary: Boundary,
+    /// The path to a level file to play on, if any.
+    pub level: Option<String>,
+    /// The path to a campaign file to play through, if any.
+    pub campaign: Option<String>,
+    /// How many snakes play on the board: 1 on your own, or 2 for a versus match.
+    #[serde(default = "default_players")]
+    pub players: usize,
+    /// How many rounds a player has to win to take a versus match.
+    #[serde(default = "default_rounds_to_win")]
+    pub rounds_to_win: usize,
+    /// Who steers each player's snake. Players past the end of the list are human.
+    #[serde(default)]
+    pub controllers: Vec<ControllerKind>,
+    /// The program to run for `external` bots, such as `python3 bots/greedy.py`.
+    pub bot_command: Option<String>,
+    /// How long an external bot gets to answer each tick, in milliseconds.
+    #[serde(default = "default_bot_timeout_ms")]
+    pub bot_timeout_ms: u64,
+    /// How long a Battlesnake bot gets to answer each request, in milliseconds.
+    #[serde(default = "default_battlesnake_timeout_ms")]
+    pub battlesnake_timeout_ms: u64,
+    /// How many ticks a key press takes to take effect in a networked game.
+    #[serde(default = "default_input_delay")]
+    pub input_delay: u64,
+    /// How long a player in a networked game can keep everyone waiting before
+    /// the host drops them, in milliseconds.
+    #[serde(default = "default_net_timeout_ms")]
+    pub net_timeout_ms: u64,
+    /// Whether networked games are played with `lockstep` or `rollback`.
+    #[serde(default)]
+    pub netcode: NetcodeKind,
+    /// How many ticks rollback can guess ahead of the other player before it
+    /// waits for them.
+    #[serde(default = "default_max_rollback")]
+    pub max_rollback: u64,
+    /// The bad network to simulate in rollback games, to try them out locally.
+    #[serde(default)]
+    pub conditions: Conditions,
+    /// Whether the snakes glide from cell to cell between ticks, rather than
+    /// jumping a whole cell each tick.
+    #[serde(default = "default_smooth_movement")]
+    pub smooth_movement: bool,
+    /// Whether the game fills the whole screen rather than opening a window.
+    #[serde(default)]
+    pub fullscreen: bool,
+    /// The biggest the window gets, in pixels. A board bigger than this scrolls
+    /// to follow the first snake.
+    #[serde(default = "default_max_window_width")]
+    pub max_window_width: u16,
+    #[serde(default = "default_max_window_height")]
+    pub max_window_height: u16,
+    /// The folder of a skin to draw the snakes and food with, such as
+    /// `assets/skins/classic`. Without one they're drawn in flat colors.
+    pub skin: Option<String>,
+    /// Whether to show how long frames take to draw, which F3 also toggles.
+    #[serde(default)]
+    pub show_frame_time: bool,
+    /// The port to stream games to spectators on, if any.
+    pub spectator_port: Option<u16>,
+    /// What agents trained with `--gym` are rewarded for.
+    #[serde(default)]
+    pub rewards: Rewards,
+}
+
+impl SnakeConfig {
+    /// The board this config describes, laid out by `level` if there is one,
+    /// or why the level can't be played with this config.
+    pub fn board(&self, level: Option<&Level>) -> Result<Board, String> {
+        match level {
+            Some(level) => level
+                .board(self)
+                .map_err(|e| format!("Error with level {}: {}", level.name, e)),
+            None => Ok(Board::new(self.grid_width, self.grid_height, self.boundary)),
+        }
+    }
+
+    /// The board for a game of `players`, or why they can't all play on it,
+    /// such as a level without a spawn for each of them.
+    pub fn board_for(&self, level: Option<&Level>, players: usize) -> Result<Board, String> {
+        let board = self.board(level)?;
+        if players > board.spawns.len() {
+            return Err(format!(
+                "The board has room for {} snakes, not {}",
+                board.spawns.len(),
+                players
+            ));
+        }
+        Ok(board)
+    }
+
+    /// Who steers the snake belonging to `player`.
+    pub fn controller(&self, player: usize) -> ControllerKind {
+        self.controllers.get(player).copied().unwrap_or_default()
+    }
+
+    /// The head and body colors of the snake belonging to `player`.
+    pub fn snake_colors(&self, player: usize) -> ([f32; 4], [f32; 4]) {
+        match player {
+            0 => (self.snake_head, self.snake_body),
+            1 => (self.snake2_head, self.snake2_body),
+            2 => (self.snake3_head, self.snake3_body),
+            _ => (self.snake4_head, self.snake4_body),
+        }
+    }
+
+    /// This config playing by the rules of the networked game `setup` describes.
+    pub fn with_setup(mut self, setup: &Setup) -> Self {
+        self.grid_width = setup.grid_width;
+        self.grid_height = setup.grid_height;
+        self.boundary = setup.boundary;
+        self.updates_per_second = setup.updates_per_second;
+        self
+    }
+
+    /// Whether switching from this config to `other` changes the board, which
+    /// means starting a new game in a window of a new size.
+    pub fn changes_board(&self, other: &SnakeConfig) -> bool {
+        self.grid_width != other.grid_width
+            || self.grid_height != other.grid_height
+            || self.cell_width != other.cell_width
+            || self.cell_height != other.cell_height
+            || self.boundary != other.boundary
+            || self.max_window_width != other.max_window_width
+            || self.max_window_height != other.max_window_height
+    }
+
+    /// The size of the whole board, in pixels.
+    pub fn board_size(&self) -> (f32, f32) {
+        (
+            self.grid_width as f32 * self.cell_width as f32,
+            self.grid_height as f32 * self.cell_height as f32,
+        )
+    }
+
+    /// The size of the window, which fits the whole board unless the board is
+    /// bigger than `max_window_width` by `max_window_height`.
+    pub fn screen_size(&self) -> (f32, f32) {
+        let (width, height) = self.board_size();
+        (
+            width.min(self.max_window_width as f32),
+            height.min(self.max_window_height as f32),
+        )
+    }
+
+    /// This config showing the game `view` is of.
+    pub fn with_view(mut self, view: &View) -> Self {
+        self.grid_width = view.width;
+        self.grid_height = view.height;
+        self.boundary = view.boundary;
+        self
+    }
+}
+
+/// Replays recorded before walls existed don't have a wall color.
+fn default_wall_color() -> [f32; 4] {
+    [0.5, 0.5, 0.5, 1.0]
+}
+
+fn default_snake2_head_color() -> [f32; 4] {
+    [0.0, 0.8, 1.0, 1.0]
+}
+
+fn default_snake2_body_color() -> [f32; 4] {
+    [0.4, 0.1, 0.5, 1.0]
+}
+
+fn default_snake3_head_color() -> [f32; 4] {
+    [1.0, 1.0, 0.3, 1.0]
+}
+
+fn default_snake3_body_color() -> [f32; 4] {
+    [0.6, 0.1, 0.1, 1.0]
+}
+
+fn default_snake4_head_color() -> [f32; 4] {
+    [1.0, 0.4, 0.8, 1.0]
+}
+
+fn default_snake4_body_color() -> [f32; 4] {
+    [0.1, 0.3, 0.6, 1.0]
+}
+
+fn default_max_window_width() -> u16 {
+    1280
+}
+
+fn default_max_window_height() -> u16 {
+    960
+}
+
+fn default_smooth_movement() -> bool {
+    true
+}
+
+fn default_players() -> usize {
+    1
+}
+
+fn default_rounds_to_win() -> usize {
+    3
+}
+
+fn default_bot_timeout_ms() -> u64 {
+    100
+}
+
+fn default_battlesnake_timeout_ms() -> u64 {
+    500
+}
+
+fn default_input_delay() -> u64 {
+    2
+}
+
+fn default_net_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_max_rollback() -> u64 {
+    8
 }